@@ -1,28 +1,31 @@
 use chrono::{DateTime, NaiveDateTime, Utc};
-use hdk::{hash_path::path::Component, prelude::*};
+use hdk::prelude::*;
 
-use crate::entries::{IndexType, StringIndex, WrappedPath};
+use crate::entries::{Index, IndexRoot, IndexType, WrappedPath};
 use crate::errors::IndexResult;
+use crate::Order;
 use crate::search::get_naivedatetime;
-use crate::utils::find_divergent_time;
+use crate::fetch::get_children_paths_batch;
+use crate::query::QueryContext;
+use crate::utils::{find_divergent_time, is_chunk_within_bounds};
 
 /// Find all paths which exist between from & until timestamps with starting index
 /// This function is executed in BFS maner and will return all paths between from/until bounds
 pub(crate) fn find_paths_for_time_span<PLT: Clone>(
     from: DateTime<Utc>,
     until: DateTime<Utc>,
-    index: String,
-    path_link_type: PLT
+    index: &IndexRoot,
+    path_link_type: PLT,
+    ctx: &mut QueryContext,
 ) -> IndexResult<Vec<Path>> 
     where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
     //Start path with index
-    let mut paths = vec![Component::from(
-        StringIndex(index).get_sb()?.bytes().to_owned(),
-    )];
+    let mut paths = vec![index.component()?];
     //Determine and create the starting path based on index and divergence between timestamps
     let (mut found_path, index_level) = find_divergent_time(&from, &until)?;
     paths.append(&mut found_path);
     let mut paths = vec![Path::from(paths)];
+    ctx.record_root(&paths[0], &index_level);
     // debug!(
     //     "Path before query starts: {:#?} starting with: {:?}",
     //     paths
@@ -34,7 +37,7 @@ pub(crate) fn find_paths_for_time_span<PLT: Clone>(
     // );
 
     for level in index_level {
-        paths = get_next_level_path_bfs(paths, &from, &until, &level, path_link_type.clone())?;
+        paths = get_next_level_path_bfs(paths, &from, &until, &level, path_link_type.clone(), ctx)?;
         // debug!(
         //     "Now have paths: {:#?} at level: {:#?}",
         //     paths
@@ -49,6 +52,39 @@ pub(crate) fn find_paths_for_time_span<PLT: Clone>(
     Ok(paths)
 }
 
+/// Find all chunks which exist under the paths between from & until timestamps and overlap them. Tree levels are enumerated in
+/// BFS maner using find_paths_for_time_span and the chunks are returned sorted in the direction given by order.
+/// from is expected to be greater than until for Order::Desc
+pub(crate) fn find_chunks_for_time_span<PLT: Clone>(
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+    index: &IndexRoot,
+    order: &Order,
+    path_link_type: PLT,
+    ctx: &mut QueryContext,
+) -> IndexResult<Vec<(Path, Index)>>
+    where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
+    let paths = match order {
+        Order::Desc => find_paths_for_time_span(until, from, index, path_link_type.clone(), ctx)?,
+        Order::Asc => find_paths_for_time_span(from, until, index, path_link_type.clone(), ctx)?,
+    };
+
+    let mut chunks = get_children_paths_batch(&paths, path_link_type, ctx)?
+        .into_iter()
+        .flatten()
+        .map(|chunk_path| {
+            let index = Index::try_from(chunk_path.clone())?;
+            Ok((chunk_path, index))
+        })
+        .collect::<IndexResult<Vec<(Path, Index)>>>()?;
+    chunks.retain(|(_chunk_path, index)| is_chunk_within_bounds(index, &from, &until, order));
+    match order {
+        Order::Desc => chunks.sort_by(|a, b| b.1.cmp(&a.1)),
+        Order::Asc => chunks.sort_by(|a, b| a.1.cmp(&b.1)),
+    };
+    Ok(chunks)
+}
+
 /// For a given index type get the naivedatetime representation of from & until and use to compare against path components
 /// found as children to supplied path. Will only return paths where path timeframe is inbetween from & until.
 /// This function is executed in bfs maner and is exhastive in that it will get all children for each path and
@@ -58,7 +94,8 @@ pub(crate) fn get_next_level_path_bfs<PLT: Clone>(
     from: &DateTime<Utc>,
     until: &DateTime<Utc>,
     index_type: &IndexType,
-    path_link_type: PLT
+    path_link_type: PLT,
+    ctx: &mut QueryContext,
 ) -> IndexResult<Vec<Path>> 
     where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
     //Get the naivedatetime representation for from & until
@@ -67,27 +104,29 @@ pub(crate) fn get_next_level_path_bfs<PLT: Clone>(
         None => return Ok(paths),
     };
 
-    //Iterate over paths and get children for each and only return paths where path is between from & until naivedatetime
+    //Get children for all paths in one call and only return paths where path is between from & until naivedatetime
     let mut out = vec![];
-    for path in paths {
-        let mut lower_paths: Vec<Path> = path.typed(path_link_type.clone())?
-            .children_paths()?
+    let mut pruned = vec![];
+    for children in get_children_paths_batch(&paths, path_link_type, ctx)? {
+        let mut lower_paths: Vec<Path> = children
             .into_iter()
             .filter_map(|path| {
-                let path_wrapped = WrappedPath(path.path.clone());
+                let path_wrapped = WrappedPath(path.clone());
                 let chrono_path: IndexResult<NaiveDateTime> = path_wrapped.try_into();
                 if chrono_path.is_err() {
                     return Some(Err(chrono_path.err().unwrap()));
                 };
                 let chrono_path = chrono_path.unwrap();
                 if chrono_path >= from_time && chrono_path <= until_time {
-                    Some(Ok(path.path))
+                    Some(Ok(path))
                 } else {
+                    pruned.push(path);
                     None
                 }
             })
             .collect::<IndexResult<Vec<Path>>>()?;    
         out.append(&mut lower_paths);
     }
+    ctx.record_level(index_type, &out, &pruned);
     Ok(out)
 }
@@ -4,42 +4,58 @@ use hdk::prelude::info::ScopedLinkType;
 use hdk::prelude::*;
 use petgraph::graph::NodeIndex;
 use petgraph::visit::Dfs;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::Debug;
 
 use crate::dfs::SearchState;
-use crate::entries::{Index, IndexType, StringIndex, WrappedPath};
+use crate::entries::{Index, IndexRoot, IndexType, WrappedPath};
 use crate::errors::{IndexError, IndexResult};
-use crate::search::get_naivedatetime;
-use crate::utils::find_divergent_time;
-use crate::{IndexableEntry, Order, DEFAULT_INDEX_DEPTH, INDEX_DEPTH};
+use crate::bfs::get_next_level_path_bfs;
+use crate::query::{get_continuation, QueryContext};
+use crate::range::NormalizedRange;
+use crate::search::{get_naivedatetime, should_expand_level_wise};
+use crate::fetch::{get_children_paths_batch, load_chunk_items, read_chunks, ChunkReads};
+use crate::utils::{find_divergent_time, is_chunk_within_bounds, is_within_bounds};
+use crate::{
+    IndexQuery, IndexableEntry, IndexedItem, Order, Page, SkippedLinks,
+    DEFAULT_INDEX_DEPTH, INDEX_DEPTH,
+};
+
+/// The index, range & query a dfs search runs over along with the link types used to read its time tree
+pub(crate) struct DfsSearch<'a, ILT, PLT> {
+    pub(crate) index: &'a IndexRoot,
+    pub(crate) range: &'a NormalizedRange,
+    pub(crate) query: &'a IndexQuery,
+    pub(crate) index_link_type: ILT,
+    pub(crate) path_link_type: PLT,
+}
 
 pub(crate) fn make_dfs_search<
     T: TryFrom<SerializedBytes, Error = SerializedBytesError> + IndexableEntry + Debug,
     ILT: LinkTypeFilterExt + Clone,
     PLT: Clone,
 >(
-    index: String,
-    from: &DateTime<Utc>,
-    until: &DateTime<Utc>,
-    order: &Order,
-    limit: Option<usize>,
-    link_tag: Option<LinkTag>,
-    index_link_type: ILT,
-    path_link_type: PLT,
-) -> IndexResult<Vec<T>>
+    search: &DfsSearch<ILT, PLT>,
+    adaptive: bool,
+    ctx: &mut QueryContext,
+    mut skipped: Option<&mut SkippedLinks>,
+) -> IndexResult<Page<IndexedItem<T>>>
 where
     ScopedLinkType: TryFrom<PLT, Error = WasmError>,
 {
-    let mut out: Vec<T> = vec![];
+    let (index, range, query) = (search.index, search.range, search.query);
+    let (index_link_type, path_link_type) = (&search.index_link_type, &search.path_link_type);
+    let (from, until, order) = (&range.from(), &range.until(), &range.order);
+    let (link_tag, sort_key, limit) = (&query.link_tag, &query.item_sort_key(), query.limit);
+    let mut out: Vec<IndexedItem<T>> = vec![];
     let mut search_state = SearchState::new();
     //Start path with index
-    let mut paths = vec![Component::from(
-        StringIndex(index).get_sb()?.bytes().to_owned(),
-    )];
+    let mut paths = vec![index.component()?];
     //Determine and create the starting path based on index and divergence between timestamps
     let (mut found_path, index_level) = find_divergent_time(&from, &until)?;
     paths.append(&mut found_path);
     let mut paths = vec![Path::from(paths)];
+    ctx.record_root(&paths[0], &index_level);
     // debug!(
     //     "Path before dfs query starts: {:#?} index levels: {:?}",
     //     paths
@@ -66,12 +82,23 @@ where
             IndexType::Minute => 5,
             IndexType::Second => 6,
         };
-        //Get the next paths for the current path
-        paths =
-            get_next_level_path_dfs(paths, &from, &until, &level, &order, path_link_type.clone())?;
+        //Get the next paths for the current path. If the budget runs out before the first leaf is reached the search fails as
+        //nothing was read which it could continue after
+        paths = get_next_level_path_dfs(
+            paths,
+            &from,
+            &until,
+            &level,
+            &order,
+            path_link_type.clone(),
+            ctx,
+        )?;
         //If we dont get any paths at the next index level then we should return empty vec
         if paths.len() == 0 {
-            return Ok(vec![]);
+            return Ok(Page {
+                results: vec![],
+                continuation: None,
+            });
         }
         // debug!(
         //     "Now have paths: {:#?} at level: {:#?}",
@@ -94,11 +121,16 @@ where
 
     //Determine how far down the graph we should search before trying to get final links/entries
     let max_depth_size = DEFAULT_INDEX_DEPTH.len() + INDEX_DEPTH.len();
-    let break_at_limit = limit.is_some();
     //Start dfs search
     let mut dfs = Dfs::new(&search_state.0, NodeIndex::from(0));
     let mut end_node = None;
-    let mut has_searched = vec![];
+    //Leaves whose chunks have been read and branches whose children have been fetched. Unlike the discovered set of dfs this is
+    //kept when the dfs is restarted after a branch is expanded
+    let mut searched: HashSet<NodeIndex> = HashSet::new();
+    let mut leaves_searched = 0;
+    //Last chunk read and whether the search stopped because it ran out of budget
+    let mut last_chunk: Option<Index> = None;
+    let mut exhausted = false;
 
     loop {
         let next_node = dfs.next(&search_state.0);
@@ -114,91 +146,81 @@ where
         if node.0.len() == max_depth_size {
             // debug!("Found node with correct depth, getting index links");
             end_node = next_node;
-            let mut indexes = Path::from(
+            if !searched.insert(next_node.unwrap()) {
+                continue;
+            };
+            if !ctx.can_afford(1, 0) {
+                exhausted = true;
+                break;
+            };
+            let leaf = Path::from(
                 search_state
                     .0
                     .node_weight(end_node.unwrap())
                     .unwrap()
                     .0
                     .clone(),
-            )
-            .typed(path_link_type.clone())?
-            .children_paths()?;
-            indexes.sort_by(|a, b| {
-                let index_chunk = Index::try_from(a.path.clone()).unwrap();
-                let index_chunk_b = Index::try_from(b.path.clone()).unwrap();
-                match order {
-                    Order::Desc => index_chunk_b.from.partial_cmp(&index_chunk.from).unwrap(),
-                    Order::Asc => index_chunk.from.partial_cmp(&index_chunk_b.from).unwrap(),
-                }
-            });
-            for index in indexes {
-                // debug!(
-                //     "Getting links for path: {:#?}",
-                //     WrappedPath(index.clone())
-                // );
-                let mut links = get_links(
-                    index.path_entry_hash()?,
-                    index_link_type.clone(),
-                    link_tag.clone(),
-                )?
+            );
+            let mut indexes = get_children_paths_batch(&[leaf], path_link_type.clone(), ctx)?
+                .pop()
+                .unwrap_or_default()
                 .into_iter()
-                .map(|link| {
-                    match get(
-                        link.target
-                            .into_entry_hash()
-                            .expect("Could not get entry hash"),
-                        GetOptions::latest(),
-                    )? {
-                        Some(chunk) => Ok(Some(chunk.entry().to_app_option::<T>()?.ok_or(
-                            IndexError::InternalError("Expected element to contain app entry data"),
-                        )?)),
-                        None => Ok(None),
-                    }
+                .map(|index| {
+                    let index_chunk = Index::try_from(index.clone())?;
+                    Ok((index, index_chunk))
                 })
-                .filter_map(|val| {
-                    if val.is_ok() {
-                        let val = val.unwrap();
-                        if val.is_some() {
-                            let val = val.unwrap();
-                            match order {
-                                Order::Desc => {
-                                    if val.entry_time() <= *from && val.entry_time() >= *until {
-                                        Some(Ok(val))
-                                    } else {
-                                        None
-                                    }
-                                }
-                                Order::Asc => {
-                                    if val.entry_time() >= *from && val.entry_time() <= *until {
-                                        Some(Ok(val))
-                                    } else {
-                                        None
-                                    }
-                                }
-                            }
-                        } else {
-                            None
-                        }
-                    } else {
-                        Some(Err(val.err().unwrap()))
-                    }
-                })
-                .collect::<IndexResult<Vec<T>>>()?;
-                out.append(&mut links);
-                if break_at_limit {
-                    if out.len() >= limit.unwrap() {
-                        break;
-                    }
-                }
-            }
-            if break_at_limit {
-                if out.len() >= limit.unwrap() {
+                .collect::<IndexResult<Vec<(Path, Index)>>>()?;
+            indexes.retain(|(_index, index_chunk)| is_chunk_within_bounds(index_chunk, from, until, order));
+            indexes.sort_by(|a, b| match order {
+                Order::Desc => b.1.from.partial_cmp(&a.1.from).unwrap(),
+                Order::Asc => a.1.from.partial_cmp(&b.1.from).unwrap(),
+            });
+            let reads = read_chunks(&indexes, 2, limit, sort_key, ctx, &mut out, |batch, ctx| {
+                Ok(load_chunk_items::<T>(batch, index_link_type.clone(), link_tag, ctx, skipped.as_deref_mut())?
+                    .into_iter()
+                    .filter(|val| {
+                        sort_key
+                            .item_time(val)
+                            .map(|time| is_within_bounds(&time, from, until, order))
+                            .unwrap_or(false)
+                    })
+                    .collect())
+            })?;
+            if reads.last_chunk.is_some() {
+                last_chunk = reads.last_chunk;
+            };
+            if reads.exhausted {
+                exhausted = true;
+                break;
+            };
+            if reads.limit_reached {
+                break;
+            };
+            leaves_searched += 1;
+            if adaptive {
+                let frontier = search_state.unexplored(&searched);
+                let remaining = limit.unwrap_or(usize::MAX).saturating_sub(out.len());
+                if should_expand_level_wise(frontier.len(), remaining, out.len(), leaves_searched) {
+                    let frontier = frontier
+                        .into_iter()
+                        .map(|node| Path::from(search_state.0.node_weight(node).unwrap().0.clone()))
+                        .collect();
+                    let reads = expand_frontier_level_wise::<T, ILT, PLT>(
+                        frontier,
+                        search,
+                        ctx,
+                        &mut out,
+                        skipped.as_deref_mut(),
+                    )?;
+                    if reads.last_chunk.is_some() {
+                        last_chunk = reads.last_chunk;
+                    };
+                    exhausted = reads.exhausted;
                     break;
                 }
             }
         } else if end_node.is_some() {
-            if !has_searched.contains(&next_node.unwrap()) {
+            if !searched.contains(&next_node.unwrap()) {
                 //Not at the bottom of the tree/graph but should be at the next lowest point of index, here we will grab then next set of indexes
                 let node = Path::from(
                     search_state
@@ -209,16 +231,12 @@ where
                         .clone(),
                 );
                 let node_components: Vec<Component> = node.clone().into();
-                let index_type = match node_components.len() {
-                    1 => IndexType::Year,
-                    2 => IndexType::Month,
-                    3 => IndexType::Day,
-                    4 => IndexType::Hour,
-                    5 => IndexType::Minute,
-                    6 => IndexType::Second,
-                    _ => return Err(IndexError::InternalError("Expected path to be length 2-7")),
-                };
+                let index_type = get_child_index_type(node_components.len())?;
                 //debug!("No node found with correct depth but node found where last end_node was of correct depth, executing next branch of search. Has index: {:#?}", next_node.unwrap());
+                if !ctx.can_afford(1, 0) {
+                    exhausted = true;
+                    break;
+                };
                 paths = get_next_level_path_dfs(
                     vec![node],
                     &from,
@@ -226,6 +244,7 @@ where
                     &index_type,
                     &order,
                     path_link_type.clone(),
+                    ctx,
                 )?;
                 // debug!(
                 //     "Got next paths in dfs search tree: {:#?}",
@@ -249,27 +268,124 @@ where
                 dfs.stack.append(&mut visited_stack);
                 dfs.stack.dedup();
 
-                //Keep array of all end nodes which were visited but required further DHT calls as to avoid infinite recursion when accessing this node again on next loop iteration
-                has_searched.push(next_node.unwrap());
+                //Keep set of all end nodes which were visited but required further DHT calls as to avoid infinite recursion when accessing this node again on next loop iteration
+                searched.insert(next_node.unwrap());
             }
         }
     }
 
     // search_state.display_dot_repr();
+    ctx.record_search_graph(&search_state);
 
-    Ok(if break_at_limit {
-        match order {
-            Order::Desc => out.sort_by(|a, b| b.entry_time().partial_cmp(&a.entry_time()).unwrap()),
-            Order::Asc => out.sort_by(|a, b| a.entry_time().partial_cmp(&b.entry_time()).unwrap()),
-        }
-        if out.len() > limit.unwrap() {
-            let _vec2 = out.split_off(limit.unwrap());
-            out
-        } else {
-            out
-        }
+    let continuation = if exhausted {
+        Some(get_continuation(last_chunk.as_ref(), order)?)
     } else {
-        out
+        None
+    };
+    Ok(Page {
+        results: out,
+        continuation,
+    })
+}
+
+/// Get the index type of the children of a path with the given number of components
+fn get_child_index_type(path_length: usize) -> IndexResult<IndexType> {
+    Ok(match path_length {
+        1 => IndexType::Year,
+        2 => IndexType::Month,
+        3 => IndexType::Day,
+        4 => IndexType::Hour,
+        5 => IndexType::Minute,
+        6 => IndexType::Second,
+        _ => return Err(IndexError::InternalError("Expected path to be length 2-7")),
+    })
+}
+
+/// Fetch every chunk below the frontier of an adaptive dfs search level by level, using one batched call per level of the tree,
+/// and read them in the order of the search with read_chunks, appending the entries in them which fall between from & until to out.
+/// Reading stops once out holds limit results as it would have in the dfs search. If the budget runs out while the tree is being
+/// walked no further chunk is read and the reads are returned as exhausted
+fn expand_frontier_level_wise<
+    T: TryFrom<SerializedBytes, Error = SerializedBytesError> + IndexableEntry + Debug,
+    ILT: LinkTypeFilterExt + Clone,
+    PLT: Clone,
+>(
+    frontier: Vec<Path>,
+    search: &DfsSearch<ILT, PLT>,
+    ctx: &mut QueryContext,
+    out: &mut Vec<IndexedItem<T>>,
+    mut skipped: Option<&mut SkippedLinks>,
+) -> IndexResult<ChunkReads>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let (range, query) = (search.range, search.query);
+    let max_depth_size = DEFAULT_INDEX_DEPTH.len() + INDEX_DEPTH.len();
+    //Level by level fetching expects from to be the earlier time
+    let (earliest, latest) = (&range.earliest, &range.latest);
+    let exhausted = ChunkReads {
+        last_chunk: None,
+        exhausted: true,
+        limit_reached: false,
+    };
+
+    let mut levels: BTreeMap<usize, Vec<Path>> = BTreeMap::new();
+    for path in frontier {
+        let components: Vec<Component> = path.clone().into();
+        levels.entry(components.len()).or_default().push(path);
+    }
+    let mut leaves = vec![];
+    while let Some((depth, paths)) = levels.pop_first() {
+        if depth >= max_depth_size {
+            leaves.extend(paths);
+            continue;
+        };
+        let children = match get_next_level_path_bfs(
+            paths,
+            earliest,
+            latest,
+            &get_child_index_type(depth)?,
+            search.path_link_type.clone(),
+            ctx,
+        ) {
+            Err(IndexError::BudgetExhausted) => return Ok(exhausted),
+            children => children?,
+        };
+        levels.entry(depth + 1).or_default().extend(children);
+    }
+
+    let mut chunks = match get_children_paths_batch(&leaves, search.path_link_type.clone(), ctx) {
+        Err(IndexError::BudgetExhausted) => return Ok(exhausted),
+        children => children?,
+    }
+    .into_iter()
+    .flatten()
+    .map(|chunk_path| {
+        let index = Index::try_from(chunk_path.clone())?;
+        Ok((chunk_path, index))
+    })
+    .filter(|chunk| {
+        chunk
+            .as_ref()
+            .map(|(_chunk_path, index)| is_chunk_within_bounds(index, earliest, latest, &Order::Asc))
+            .unwrap_or(true)
+    })
+    .collect::<IndexResult<Vec<(Path, Index)>>>()?;
+    chunks.sort_by(|a, b| match range.order {
+        Order::Desc => b.1.cmp(&a.1),
+        Order::Asc => a.1.cmp(&b.1),
+    });
+    let sort_key = query.item_sort_key();
+    read_chunks(&chunks, 2, query.limit, &sort_key, ctx, out, |batch, ctx| {
+        Ok(load_chunk_items::<T>(batch, search.index_link_type.clone(), &query.link_tag, ctx, skipped.as_deref_mut())?
+            .into_iter()
+            .filter(|item| {
+                sort_key
+                    .item_time(item)
+                    .map(|time| is_within_bounds(&time, &range.from(), &range.until(), &range.order))
+                    .unwrap_or(false)
+            })
+            .collect())
     })
 }
 
@@ -284,6 +400,7 @@ pub(crate) fn get_next_level_path_dfs<PLT: Clone>(
     index_type: &IndexType,
     order: &Order,
     path_link_type: PLT,
+    ctx: &mut QueryContext,
 ) -> IndexResult<Vec<Path>>
 where
     ScopedLinkType: TryFrom<PLT, Error = WasmError>,
@@ -307,13 +424,14 @@ where
     // debug!("Got chosen path: {:#?}", WrappedPath(chosen_path.clone()));
 
     //Iterate over paths and get children for each and only return paths where path is between from & until naivedatetime
-    let mut lower_paths: Vec<Path> = chosen_path
-        .typed(path_link_type)?
-        .children_paths()?
+    let mut pruned = vec![];
+    let mut lower_paths: Vec<Path> = get_children_paths_batch(&[chosen_path], path_link_type, ctx)?
+        .pop()
+        .unwrap_or_default()
         .into_iter()
         .filter_map(|path| {
             // debug!("Got path in map {:#?}", path);
-            let path_wrapped = WrappedPath(path.path.clone());
+            let path_wrapped = WrappedPath(path.clone());
             let chrono_path: IndexResult<NaiveDateTime> = path_wrapped.clone().try_into();
             // debug!("Got path in lowerpaths fn: {:#?}. {:#?}. {:#?}/{:#?}. {:#?}", path_wrapped, chrono_path, from_time, until_time, index_type);
             if chrono_path.is_err() {
@@ -323,21 +441,24 @@ where
             match order {
                 Order::Desc => {
                     if chrono_path <= from_time && chrono_path >= until_time {
-                        Some(Ok(path.path))
+                        Some(Ok(path))
                     } else {
+                        pruned.push(path);
                         None
                     }
                 }
                 Order::Asc => {
                     if chrono_path >= from_time && chrono_path <= until_time {
-                        Some(Ok(path.path))
+                        Some(Ok(path))
                     } else {
+                        pruned.push(path);
                         None
                     }
                 }
             }
         })
         .collect::<IndexResult<Vec<Path>>>()?;
+    ctx.record_level(index_type, &lower_paths, &pruned);
     lower_paths.sort_by(|a, b| {
         let path_wrapped = WrappedPath(a.clone());
         let path_wrapped_b = WrappedPath(b.clone());
@@ -1,5 +1,7 @@
 use hdk::hash_path::path::{Component, Path};
+use petgraph::dot::{Config, Dot};
 use petgraph::{graph::NodeIndex, stable_graph::StableDiGraph};
+use std::collections::HashSet;
 use std::convert::TryFrom;
 
 use crate::entries::{Index, StringIndex, TimeIndex};
@@ -121,8 +123,80 @@ impl SearchState {
         Ok(added_indexes)
     }
 
+    /// Nodes whose children have not been fetched and which are not in searched, ordered by when they were added to the graph.
+    /// These are the parts of the tree a search has yet to visit
+    pub(crate) fn unexplored(&self, searched: &HashSet<NodeIndex>) -> Vec<NodeIndex> {
+        self.0
+            .node_indices()
+            .filter(|node| !searched.contains(node) && self.0.neighbors(*node).next().is_none())
+            .collect()
+    }
+
+    /// DOT representation of graph state
+    pub(crate) fn to_dot(&self) -> String {
+        format!("{:?}", Dot::with_config(&self.0, &[Config::EdgeNoLabel]))
+    }
+
     // // /// Holochain debug dot representation of graph state
     // pub(crate) fn display_dot_repr(&self) {
     //     debug!("{:#?}", Dot::new(&self.0));
     // }
 }
+
+mod search_state_tests {
+    #[test]
+    fn test_unexplored() {
+        use crate::dfs::SearchState;
+        use hdk::hash_path::path::{Component, Path};
+        use petgraph::graph::NodeIndex;
+        use std::collections::HashSet;
+
+        let path = |components: &[&str]| {
+            Path::from(
+                components
+                    .iter()
+                    .map(|component| Component::from(*component))
+                    .collect::<Vec<Component>>(),
+            )
+        };
+        let mut search_state = SearchState::new();
+        search_state
+            .populate_from_paths(&vec![path(&["index", "2021"])], 0)
+            .unwrap();
+        let year = NodeIndex::new(1);
+        let months = search_state
+            .populate_next_nodes_from_position(
+                vec![path(&["index", "2021", "8"]), path(&["index", "2021", "7"])],
+                year,
+            )
+            .unwrap();
+        let days = search_state
+            .populate_next_nodes_from_position(
+                vec![
+                    path(&["index", "2021", "8", "12"]),
+                    path(&["index", "2021", "8", "11"]),
+                ],
+                months[0],
+            )
+            .unwrap();
+
+        //The first day has been read so the other day and the month which has not been expanded are left
+        let mut searched = HashSet::from([days[0]]);
+        assert_eq!(search_state.unexplored(&searched), vec![months[1], days[1]]);
+
+        //Expanding the other month adds its days, which have not been visited yet
+        let more_days = search_state
+            .populate_next_nodes_from_position(vec![path(&["index", "2021", "7", "30"])], months[1])
+            .unwrap();
+        searched.insert(months[1]);
+        assert_eq!(
+            search_state.unexplored(&searched),
+            vec![days[1], more_days[0]]
+        );
+
+        //A branch which was expanded but had no children is not left to visit
+        searched.insert(more_days[0]);
+        searched.insert(days[1]);
+        assert!(search_state.unexplored(&searched).is_empty());
+    }
+}
@@ -12,6 +12,18 @@ pub struct Index {
 #[derive(Clone, SerializedBytes, Debug, Serialize, Deserialize)]
 pub struct StringIndex(pub String);
 
+/// Time tree being written to or searched. Besides the indexes named by callers the crate keeps trees of its own for an index.
+/// The root component of those trees is serialized as a map rather than as a string, like a StringIndex is, so they can never share a
+/// root path with an index whatever its name
+#[derive(Clone, SerializedBytes, Debug, Serialize, Deserialize)]
+pub(crate) enum IndexRoot {
+    Index(String),
+    /// Changes made to the index, see IndexConfiguration::ingest_log
+    IngestLog(String),
+    /// Agents subscribed to the index
+    Subscribers(String),
+}
+
 #[derive(Clone, Eq, PartialEq, SerializedBytes, Debug, Serialize, Deserialize)]
 pub struct TimeIndex(pub u32);
 
@@ -19,7 +31,7 @@ pub struct TimeIndex(pub u32);
 #[derive(Clone)]
 pub struct WrappedPath(pub Path);
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum IndexType {
     Year,
     Month,
@@ -17,6 +17,10 @@ pub enum IndexError {
     // HdkError(#[from] HdkError),
     #[error("Invalid Request Data. Error: {0}")]
     RequestError(&'static str),
+    /// Raised inside of a search when its QueryBudget does not allow a further host call or chunk read. Searches stop at this
+    /// point and return the results found so far along with a continuation
+    #[error("Query budget exhausted")]
+    BudgetExhausted,
 }
 
 pub type IndexResult<T> = Result<T, IndexError>;
@@ -0,0 +1,72 @@
+use hdk::prelude::*;
+
+use crate::entries::{Index, IndexType};
+
+/// Report of how a search walked the time tree. Returned by the explain variants of the search functions
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct QueryPlan {
+    /// Path where from & until diverge as found by find_divergent_time. The search starts below this path
+    pub root: Option<Path>,
+    /// Levels of the tree searched below root
+    pub root_levels: Vec<IndexType>,
+    /// Paths visited and pruned at each level of the tree, in the order the levels were first reached
+    pub levels: Vec<LevelReport>,
+    /// Number of batched get_links host calls made
+    pub get_links_calls: usize,
+    /// Number of batched get host calls made
+    pub get_calls: usize,
+    /// Chunks whose links were read, in the order they were read
+    pub chunks_read: Vec<Index>,
+    /// DOT representation of the graph of paths discovered by a DFS search. None for BFS searches
+    pub search_graph: Option<String>,
+}
+
+/// Paths found at one level of the time tree during a search
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LevelReport {
+    pub level: IndexType,
+    /// Paths inside of the searched time span
+    pub visited: Vec<Path>,
+    /// Paths which were found but are outside of the searched time span so were not descended into
+    pub pruned: Vec<Path>,
+}
+
+impl QueryPlan {
+    /// Add paths found at level to the report for that level
+    pub(crate) fn record_level(&mut self, level: &IndexType, visited: &[Path], pruned: &[Path]) {
+        let report = match self.levels.iter_mut().position(|report| &report.level == level) {
+            Some(position) => &mut self.levels[position],
+            None => {
+                self.levels.push(LevelReport {
+                    level: level.clone(),
+                    visited: vec![],
+                    pruned: vec![],
+                });
+                self.levels.last_mut().unwrap()
+            }
+        };
+        report.visited.extend_from_slice(visited);
+        report.pruned.extend_from_slice(pruned);
+    }
+}
+
+mod explain_tests {
+    #[test]
+    fn test_record_level() {
+        use crate::entries::IndexType;
+        use crate::explain::QueryPlan;
+        use hdk::prelude::*;
+
+        let mut plan = QueryPlan::default();
+        let path = |name: &str| Path::from(name);
+        plan.record_level(&IndexType::Year, &[path("2021")], &[path("2019")]);
+        plan.record_level(&IndexType::Month, &[path("2021.1")], &[]);
+        plan.record_level(&IndexType::Year, &[path("2022")], &[]);
+
+        assert_eq!(plan.levels.len(), 2);
+        assert_eq!(plan.levels[0].level, IndexType::Year);
+        assert_eq!(plan.levels[0].visited, vec![path("2021"), path("2022")]);
+        assert_eq!(plan.levels[0].pruned, vec![path("2019")]);
+        assert_eq!(plan.levels[1].visited, vec![path("2021.1")]);
+    }
+}
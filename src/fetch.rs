@@ -0,0 +1,423 @@
+use hdk::{hash_path::path::Component, prelude::*};
+
+use crate::entries::Index;
+use crate::errors::{IndexError, IndexResult};
+use crate::query::QueryContext;
+use crate::{IndexedItem, LinkTagFilter, SkippedLinks, SortKey};
+
+/// Get the children of each of the supplied paths using a single get_links host call. Results are returned in the same order as paths.
+/// Paths whose children are known by the query's snapshot are not fetched and fetched children are added to the snapshot.
+/// Unlike TypedPath::children_paths() this does not try to create the supplied paths if they do not exist yet
+pub(crate) fn get_children_paths_batch<PLT>(
+    paths: &[Path],
+    path_link_type: PLT,
+    ctx: &mut QueryContext,
+) -> IndexResult<Vec<Vec<Path>>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    //Children already known from the query's snapshot do not need to be fetched again
+    let mut children = paths
+        .iter()
+        .map(|path| {
+            ctx.options
+                .snapshot
+                .as_ref()
+                .and_then(|snapshot| snapshot.borrow().get_children(path).cloned())
+        })
+        .collect::<Vec<Option<Vec<Path>>>>();
+    let missing = paths
+        .iter()
+        .zip(children.iter())
+        .filter(|(_path, children)| children.is_none())
+        .map(|(path, _children)| path)
+        .collect::<Vec<&Path>>();
+    if missing.is_empty() {
+        return Ok(children.into_iter().flatten().collect());
+    };
+
+    let link_type = ScopedLinkType::try_from(path_link_type)?;
+    let link_type = LinkTypeFilter::single_type(link_type.zome_index, link_type.zome_type);
+    let inputs = missing
+        .iter()
+        .map(|path| {
+            Ok(GetLinksInput::new(
+                path.path_entry_hash()?.into(),
+                link_type.clone(),
+                None,
+            ))
+        })
+        .collect::<IndexResult<Vec<GetLinksInput>>>()?;
+    ctx.record_get_links()?;
+    let links = HDK.with(|h| h.borrow().get_links(inputs))?;
+
+    let mut fetched = missing
+        .into_iter()
+        .zip(links)
+        .map(|(path, mut links)| {
+            //Only need one of each tag to build the tree
+            links.sort_unstable_by(|a, b| a.tag.cmp(&b.tag));
+            links.dedup_by(|a, b| a.tag.eq(&b.tag));
+            let path_children = links
+                .into_iter()
+                .filter(|link| !link.tag.0.is_empty())
+                .map(|link| {
+                    let component = Component::try_from(SerializedBytes::from(UnsafeBytes::from(
+                        link.tag.into_inner(),
+                    )))?;
+                    let mut child = path.clone();
+                    child.append_component(component);
+                    Ok(child)
+                })
+                .collect::<IndexResult<Vec<Path>>>()?;
+            if let Some(snapshot) = ctx.options.snapshot.as_ref() {
+                snapshot.borrow_mut().insert(path, path_children.clone());
+            };
+            Ok(path_children)
+        })
+        .collect::<IndexResult<Vec<Vec<Path>>>>()?
+        .into_iter();
+    for path_children in children.iter_mut() {
+        if path_children.is_none() {
+            *path_children = fetched.next();
+        };
+    }
+    Ok(children.into_iter().map(|path_children| path_children.unwrap_or_default()).collect())
+}
+
+/// Get links from base and only return the ones whose tag is accepted by link_tag filter
+pub(crate) fn get_filtered_links(
+    base: impl Into<AnyLinkableHash>,
+    link_type: impl LinkTypeFilterExt,
+    link_tag: &LinkTagFilter,
+) -> IndexResult<Vec<Link>> {
+    Ok(get_filtered_links_batch(vec![base.into()], link_type, link_tag, &mut QueryContext::default())?
+        .pop()
+        .unwrap_or_default())
+}
+
+/// Create link action along with the delete link actions on it
+pub(crate) type LinkWithDeletes = (SignedActionHashed, Vec<SignedActionHashed>);
+
+/// Get the create link actions, along with any delete link actions on them, from each of the supplied bases using a single
+/// get_link_details host call and only return the ones whose tag is accepted by link_tag filter. Results are returned in the same
+/// order as bases
+pub(crate) fn get_filtered_link_details_batch(
+    bases: Vec<AnyLinkableHash>,
+    link_type: impl LinkTypeFilterExt,
+    link_tag: &LinkTagFilter,
+    ctx: &mut QueryContext,
+) -> IndexResult<Vec<Vec<LinkWithDeletes>>> {
+    if bases.is_empty() {
+        return Ok(vec![]);
+    };
+    let link_type = link_type.try_into_filter()?;
+    let prefix = link_tag.get_links_prefix();
+    let inputs = bases
+        .into_iter()
+        .map(|base| GetLinksInput::new(base, link_type.clone(), prefix.clone()))
+        .collect();
+    ctx.record_get_links()?;
+    let details = HDK.with(|h| h.borrow().get_link_details(inputs))?;
+    Ok(details
+        .into_iter()
+        .map(|details| {
+            details
+                .into_inner()
+                .into_iter()
+                .filter(|(create, _deletes)| match create.action() {
+                    Action::CreateLink(create_link) => link_tag.matches(&create_link.tag),
+                    _ => false,
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Build the link created by a create link action. Returns None if the action is not a create link
+pub(crate) fn link_from_create(create: &SignedActionHashed) -> Option<Link> {
+    match create.action() {
+        Action::CreateLink(create_link) => Some(Link {
+            target: create_link.target_address.clone(),
+            timestamp: create_link.timestamp,
+            zome_index: create_link.zome_index,
+            link_type: create_link.link_type,
+            tag: create_link.tag.clone(),
+            create_link_hash: create.action_address().clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// Get links from each of the supplied bases using a single get_links host call and only return the ones whose tag is accepted
+/// by link_tag filter. Results are returned in the same order as bases
+pub(crate) fn get_filtered_links_batch(
+    bases: Vec<AnyLinkableHash>,
+    link_type: impl LinkTypeFilterExt,
+    link_tag: &LinkTagFilter,
+    ctx: &mut QueryContext,
+) -> IndexResult<Vec<Vec<Link>>> {
+    if bases.is_empty() {
+        return Ok(vec![]);
+    };
+    let link_type = link_type.try_into_filter()?;
+    let prefix = link_tag.get_links_prefix();
+    let inputs = bases
+        .into_iter()
+        .map(|base| GetLinksInput::new(base, link_type.clone(), prefix.clone()))
+        .collect();
+    ctx.record_get_links()?;
+    let links = HDK.with(|h| h.borrow().get_links(inputs))?;
+    Ok(match link_tag {
+        LinkTagFilter::All | LinkTagFilter::Prefix(_) => links,
+        _ => links
+            .into_iter()
+            .map(|links| {
+                links
+                    .into_iter()
+                    .filter(|link| link_tag.matches(&link.tag))
+                    .collect()
+            })
+            .collect(),
+    })
+}
+
+/// Decode the record an index link points to into an IndexedItem
+fn decode_indexed_item<T: TryFrom<SerializedBytes, Error = SerializedBytesError>>(
+    link: Link,
+    index: Index,
+    record: Record,
+) -> IndexResult<IndexedItem<T>> {
+    let entry = record.entry().to_app_option::<T>()?.ok_or(IndexError::InternalError(
+        "Expected element to contain app entry data",
+    ))?;
+    Ok(IndexedItem {
+        link,
+        index,
+        action: record.signed_action,
+        entry,
+    })
+}
+
+/// Load the targets for a set of index links using a single get host call made with the query's get options. Targets which could not be found are not returned.
+/// If skipped is provided then links whose target could not be found or loaded are added to it alongside the error, otherwise the first
+/// error loading a target is returned
+pub(crate) fn load_indexed_items<T: TryFrom<SerializedBytes, Error = SerializedBytesError>>(
+    links: Vec<(Link, Index)>,
+    ctx: &mut QueryContext,
+    mut skipped: Option<&mut SkippedLinks>,
+) -> IndexResult<Vec<IndexedItem<T>>> {
+    let mut skip_or_fail = |link: Link, err: IndexError| match skipped {
+        Some(ref mut skipped) => {
+            skipped.push((link, err));
+            Ok(())
+        }
+        None => Err(err),
+    };
+
+    let mut inputs = vec![];
+    let mut pending = vec![];
+    for (link, index) in links {
+        match link.target.clone().into_entry_hash() {
+            Some(target) => {
+                inputs.push(GetInput::new(target.into(), ctx.options.get_options.clone()));
+                pending.push((link, index));
+            }
+            None => skip_or_fail(
+                link,
+                IndexError::InternalError("Expected link target to be an entry hash"),
+            )?,
+        }
+    }
+    if inputs.is_empty() {
+        return Ok(vec![]);
+    };
+    ctx.record_get()?;
+    let records = HDK.with(|h| h.borrow().get(inputs))?;
+
+    let mut out = vec![];
+    let mut missing = vec![];
+    for ((link, index), record) in pending.into_iter().zip(records) {
+        match record {
+            Some(record) => match decode_indexed_item::<T>(link.clone(), index, record) {
+                Ok(item) => out.push(item),
+                Err(err) => skip_or_fail(link, err)?,
+            },
+            None => missing.push(link),
+        }
+    }
+    //Links to targets which have not been found yet are left out of strict searches rather than failing them
+    if let Some(skipped) = skipped {
+        skipped.extend(
+            missing
+                .into_iter()
+                .map(|link| (link, IndexError::InternalError("Could not find link target"))),
+        );
+    };
+    Ok(out)
+}
+
+/// Load the entries indexed under each of the supplied chunks. Links for all chunks are fetched in a single get_links call
+/// and their targets in a single get call. Fails with IndexError::BudgetExhausted if the query's budget does not allow the chunks
+/// to be read
+pub(crate) fn load_chunk_items<T: TryFrom<SerializedBytes, Error = SerializedBytesError>>(
+    chunks: &[(Path, Index)],
+    index_link_type: impl LinkTypeFilterExt,
+    link_tag: &LinkTagFilter,
+    ctx: &mut QueryContext,
+    skipped: Option<&mut SkippedLinks>,
+) -> IndexResult<Vec<IndexedItem<T>>> {
+    ctx.record_chunks(chunks)?;
+    let bases = chunks
+        .iter()
+        .map(|(chunk_path, _index)| Ok(chunk_path.path_entry_hash()?.into()))
+        .collect::<IndexResult<Vec<AnyLinkableHash>>>()?;
+    let links = get_filtered_links_batch(bases, index_link_type, link_tag, ctx)?
+        .into_iter()
+        .zip(chunks.iter())
+        .flat_map(|(links, (_chunk_path, index))| {
+            links.into_iter().map(move |link| (link, index.clone()))
+        })
+        .collect();
+    load_indexed_items(links, ctx, skipped)
+}
+
+/// Where reading chunks with read_chunks stopped
+pub(crate) struct ChunkReads {
+    /// Last chunk which was read
+    pub(crate) last_chunk: Option<Index>,
+    /// Reading stopped because the query's budget did not allow the next batch of chunks to be read
+    pub(crate) exhausted: bool,
+    /// Reading stopped because limit results had been found
+    pub(crate) limit_reached: bool,
+}
+
+/// Read chunks, which are expected to be in the order of the search, with read and append the results to out. Reading each batch
+/// of chunks is expected to take host_calls host calls.
+/// Chunks are visited in time order, so when results are ordered by the time of the chunk they are in no later chunk can contain
+/// a result which should be returned before the ones already found and reading stops once out holds limit results. When reading
+/// cannot stop early, and the query has no budget to stop at, all chunks are read as a single batch so that the links and
+/// entries in them are fetched with one host call each. Otherwise chunks are read one at a time
+pub(crate) fn read_chunks<R>(
+    chunks: &[(Path, Index)],
+    host_calls: usize,
+    limit: Option<usize>,
+    sort_key: &SortKey,
+    ctx: &mut QueryContext,
+    out: &mut Vec<R>,
+    mut read: impl FnMut(&[(Path, Index)], &mut QueryContext) -> IndexResult<Vec<R>>,
+) -> IndexResult<ChunkReads> {
+    let stop_at = limit.filter(|_limit| matches!(sort_key, SortKey::EntryTime));
+    let batch_size = if stop_at.is_some() || ctx.has_budget() { 1 } else { chunks.len().max(1) };
+    let mut reads = ChunkReads {
+        last_chunk: None,
+        exhausted: false,
+        limit_reached: stop_at.map(|limit| out.len() >= limit).unwrap_or(false),
+    };
+    for batch in chunks.chunks(batch_size) {
+        if reads.limit_reached {
+            break;
+        };
+        if !ctx.can_afford(host_calls, batch.len()) {
+            reads.exhausted = true;
+            break;
+        };
+        out.append(&mut read(batch, ctx)?);
+        reads.last_chunk = batch.last().map(|(_chunk_path, index)| index.clone());
+        reads.limit_reached = stop_at.map(|limit| out.len() >= limit).unwrap_or(false);
+    }
+    Ok(reads)
+}
+
+mod fetch_tests {
+    #[cfg(test)]
+    fn chunks(count: u64) -> Vec<(hdk::prelude::Path, crate::entries::Index)> {
+        use crate::entries::Index;
+        use hdk::prelude::*;
+        use std::time::Duration;
+
+        (0..count)
+            .map(|chunk| {
+                let index = Index {
+                    from: Duration::from_secs(chunk * 10),
+                    until: Duration::from_secs(chunk * 10 + 10),
+                };
+                (Path::from(format!("chunk{}", chunk)), index)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_read_chunks_stops_at_limit() {
+        use crate::fetch::read_chunks;
+        use crate::query::QueryContext;
+        use crate::SortKey;
+
+        let chunks = chunks(5);
+        let mut batches = vec![];
+        let mut out = vec![];
+        let reads = read_chunks(&chunks, 2, Some(3), &SortKey::EntryTime, &mut QueryContext::default(), &mut out, |batch, _ctx| {
+            batches.push(batch.len());
+            Ok(vec![batch[0].1.clone(), batch[0].1.clone()])
+        })
+        .unwrap();
+        //Chunks are read one at a time and reading stops after the second chunk reaches the limit
+        assert_eq!(batches, vec![1, 1]);
+        assert_eq!(out.len(), 4);
+        assert!(reads.limit_reached);
+        assert!(!reads.exhausted);
+        assert_eq!(reads.last_chunk, Some(chunks[1].1.clone()));
+
+        //Reading more chunks into a full out reads nothing
+        let reads = read_chunks(&chunks, 2, Some(3), &SortKey::EntryTime, &mut QueryContext::default(), &mut out, |_batch, _ctx| {
+            panic!("Should not read a chunk once limit results were found")
+        })
+        .unwrap();
+        assert!(reads.limit_reached);
+        assert_eq!(reads.last_chunk, None);
+    }
+
+    #[test]
+    fn test_read_chunks_batches() {
+        use crate::fetch::read_chunks;
+        use crate::query::QueryContext;
+        use crate::{QueryBudget, QueryOptions, SortKey};
+
+        let chunks = chunks(5);
+        //Without a limit to stop at every chunk is read in a single batch
+        let mut batches = vec![];
+        let mut out: Vec<usize> = vec![];
+        read_chunks(&chunks, 2, None, &SortKey::EntryTime, &mut QueryContext::default(), &mut out, |batch, _ctx| {
+            batches.push(batch.len());
+            Ok(vec![batch.len()])
+        })
+        .unwrap();
+        assert_eq!(batches, vec![5]);
+
+        //Results ordered by link creation can be in any chunk so the limit does not stop reading
+        let mut batches = vec![];
+        let reads = read_chunks(&chunks, 2, Some(1), &SortKey::LinkCreated, &mut QueryContext::default(), &mut vec![], |batch, _ctx| {
+            batches.push(batch.len());
+            Ok(vec![batch.len()])
+        })
+        .unwrap();
+        assert_eq!(batches, vec![5]);
+        assert!(!reads.limit_reached);
+
+        //Searches with a budget read one chunk at a time and stop once the budget runs out
+        let mut ctx = QueryContext::new(QueryOptions::default().with_budget(QueryBudget {
+            max_host_calls: None,
+            max_chunks: Some(2),
+        }));
+        let mut batches = vec![];
+        let reads = read_chunks(&chunks, 2, None, &SortKey::EntryTime, &mut ctx, &mut vec![], |batch, ctx| {
+            ctx.record_chunks(batch)?;
+            batches.push(batch.len());
+            Ok(vec![batch.len()])
+        })
+        .unwrap();
+        assert_eq!(batches, vec![1, 1]);
+        assert!(reads.exhausted);
+        assert_eq!(reads.last_chunk, Some(chunks[1].1.clone()));
+    }
+}
@@ -1,10 +1,14 @@
 use chrono::NaiveDateTime;
 use std::{convert::TryInto, ops::Sub};
 
-use hdk::prelude::SerializedBytes;
+use hdk::hash_path::path::Component;
+use hdk::prelude::{LinkTag, SerializedBytes, TryFrom};
+use serde::de::DeserializeOwned;
+use std::rc::Rc;
 
-use crate::entries::{Index, StringIndex, TimeIndex};
+use crate::entries::{Index, IndexRoot, StringIndex, TimeIndex};
 use crate::errors::IndexResult;
+use crate::{decode_link_tag, LinkTagFilter};
 
 /// Helper function to get serializedbytes of StringIndex and make this cleaner in the code
 impl StringIndex {
@@ -13,6 +17,17 @@ impl StringIndex {
     }
 }
 
+/// Helper function to get the first component of the paths in a time tree
+impl IndexRoot {
+    pub(crate) fn component(&self) -> IndexResult<Component> {
+        let bytes = match self {
+            IndexRoot::Index(index) => StringIndex(index.clone()).get_sb()?,
+            root => SerializedBytes::try_from(root.clone())?,
+        };
+        Ok(Component::from(bytes.bytes().to_owned()))
+    }
+}
+
 /// Helper function to get serializedbytes of TimeIndex and make this cleaner in the code
 impl TimeIndex {
     pub fn get_sb(self) -> IndexResult<SerializedBytes> {
@@ -37,3 +52,163 @@ impl std::fmt::Debug for Index {
         debug_struct.finish()
     }
 }
+
+impl LinkTagFilter {
+    /// Check if a given link tag is accepted by this filter
+    pub fn matches(&self, tag: &LinkTag) -> bool {
+        match self {
+            LinkTagFilter::All => true,
+            LinkTagFilter::Exact(exact) => tag == exact,
+            LinkTagFilter::Prefix(prefix) => tag.as_ref().starts_with(prefix.as_ref()),
+            LinkTagFilter::AnyOf(filters) => filters.iter().any(|filter| filter.matches(tag)),
+            LinkTagFilter::Predicate(predicate) => predicate(tag),
+        }
+    }
+
+    /// Create a predicate filter which is run against tags decoded into Tag. Links whose tag cannot be decoded are rejected
+    pub fn decoded<Tag: DeserializeOwned + std::fmt::Debug>(
+        predicate: impl Fn(&Tag) -> bool + 'static,
+    ) -> LinkTagFilter {
+        LinkTagFilter::Predicate(Rc::new(move |tag: &LinkTag| {
+            decode_link_tag::<Tag>(tag)
+                .map(|tag| predicate(&tag))
+                .unwrap_or(false)
+        }))
+    }
+
+    /// Longest tag prefix that every link accepted by this filter must start with; used as the tag argument for get_links
+    pub(crate) fn get_links_prefix(&self) -> Option<LinkTag> {
+        match self {
+            LinkTagFilter::All => None,
+            LinkTagFilter::Exact(tag) => Some(tag.clone()),
+            LinkTagFilter::Prefix(prefix) => Some(prefix.clone()),
+            LinkTagFilter::AnyOf(filters) => {
+                let mut prefixes = filters.iter().map(|filter| filter.get_links_prefix());
+                let mut common: Vec<u8> = prefixes.next()??.into_inner();
+                for prefix in prefixes {
+                    let prefix = prefix?;
+                    let shared = common
+                        .iter()
+                        .zip(prefix.as_ref().iter())
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    common.truncate(shared);
+                }
+                if common.is_empty() {
+                    None
+                } else {
+                    Some(LinkTag::new(common))
+                }
+            }
+            LinkTagFilter::Predicate(_) => None,
+        }
+    }
+}
+
+impl From<Option<LinkTag>> for LinkTagFilter {
+    fn from(tag: Option<LinkTag>) -> Self {
+        match tag {
+            Some(tag) => LinkTagFilter::Prefix(tag),
+            None => LinkTagFilter::All,
+        }
+    }
+}
+
+impl std::fmt::Debug for LinkTagFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkTagFilter::All => write!(f, "All"),
+            LinkTagFilter::Exact(tag) => f.debug_tuple("Exact").field(tag).finish(),
+            LinkTagFilter::Prefix(tag) => f.debug_tuple("Prefix").field(tag).finish(),
+            LinkTagFilter::AnyOf(filters) => f.debug_tuple("AnyOf").field(filters).finish(),
+            LinkTagFilter::Predicate(_) => write!(f, "Predicate(..)"),
+        }
+    }
+}
+
+mod link_tag_filter_tests {
+    #[test]
+    fn test_filter_matches() {
+        use crate::LinkTagFilter;
+        use hdk::prelude::LinkTag;
+        use std::rc::Rc;
+
+        let tag = LinkTag::new("message:general");
+        assert!(LinkTagFilter::All.matches(&tag));
+        assert!(LinkTagFilter::Prefix(LinkTag::new("message")).matches(&tag));
+        assert!(!LinkTagFilter::Exact(LinkTag::new("message")).matches(&tag));
+        assert!(LinkTagFilter::Exact(LinkTag::new("message:general")).matches(&tag));
+        assert!(LinkTagFilter::AnyOf(vec![
+            LinkTagFilter::Exact(LinkTag::new("reaction:general")),
+            LinkTagFilter::Prefix(LinkTag::new("message:")),
+        ])
+        .matches(&tag));
+        assert!(!LinkTagFilter::Predicate(Rc::new(|tag: &LinkTag| tag.as_ref().len() < 4))
+            .matches(&tag));
+    }
+
+    #[test]
+    fn test_filter_get_links_prefix() {
+        use crate::LinkTagFilter;
+        use hdk::prelude::LinkTag;
+
+        let any_of = LinkTagFilter::AnyOf(vec![
+            LinkTagFilter::Exact(LinkTag::new("message:general")),
+            LinkTagFilter::Prefix(LinkTag::new("message:random")),
+        ]);
+        assert_eq!(any_of.get_links_prefix(), Some(LinkTag::new("message:")));
+        let disjoint = LinkTagFilter::AnyOf(vec![
+            LinkTagFilter::Prefix(LinkTag::new("message")),
+            LinkTagFilter::Prefix(LinkTag::new("reaction")),
+        ]);
+        assert_eq!(disjoint.get_links_prefix(), None);
+        let with_all = LinkTagFilter::AnyOf(vec![
+            LinkTagFilter::Prefix(LinkTag::new("message")),
+            LinkTagFilter::All,
+        ]);
+        assert_eq!(with_all.get_links_prefix(), None);
+    }
+
+    #[test]
+    fn test_decoded_filter() {
+        use crate::{encode_link_tag, LinkTagFilter};
+        use hdk::prelude::*;
+
+        #[derive(Serialize, Deserialize, Debug)]
+        struct MessageTag {
+            content_type: String,
+        }
+
+        let tag = encode_link_tag(&MessageTag {
+            content_type: String::from("image"),
+        })
+        .unwrap();
+        let images = LinkTagFilter::decoded(|tag: &MessageTag| tag.content_type == "image");
+        let text = LinkTagFilter::decoded(|tag: &MessageTag| tag.content_type == "text");
+        assert!(images.matches(&tag));
+        assert!(!text.matches(&tag));
+        assert!(!images.matches(&LinkTag::new("not msgpack")));
+    }
+}
+
+mod index_root_tests {
+    #[test]
+    fn test_derived_roots_do_not_collide() {
+        use crate::entries::{IndexRoot, StringIndex};
+        use hdk::hash_path::path::Component;
+
+        let index = IndexRoot::Index(String::from("posts")).component().unwrap();
+        let ingest_log = IndexRoot::IngestLog(String::from("posts")).component().unwrap();
+        let subscribers = IndexRoot::Subscribers(String::from("posts")).component().unwrap();
+        assert_eq!(
+            index,
+            Component::from(StringIndex(String::from("posts")).get_sb().unwrap().bytes().to_owned())
+        );
+        assert_ne!(index, ingest_log);
+        assert_ne!(ingest_log, subscribers);
+        //Indexes named the way derived trees used to be named are kept apart from them
+        for (name, derived) in [("posts.ingest_log", &ingest_log), ("posts.subscribers", &subscribers)] {
+            assert_ne!(&IndexRoot::Index(String::from(name)).component().unwrap(), derived);
+        }
+    }
+}
@@ -27,6 +27,8 @@
 //!
 //! For any given index an **agent** cannot make more than `ENFORCE_SPAM_LIMIT` links. This value is set by the properties of the host DNA which is using this library; this library will just read host DNA's properties and set its internal variables based on what it finds.
 //!
+//! Chunks may not start in the future. Agents whose clocks run slightly ahead can be tolerated by setting `max_future_skew_ms` in the host DNA's properties; the same tolerance is used when authoring and validating chunks.
+//!
 //! ### DNA Lifecycle
 //!
 //! This DNA's variables mentioned above are expected to be static. That means its expected that the: `ENFORCE_SPAM_LIMIT` & `MAX_CHUNK_INTERVAL` should stay the same throughout the lifetime of the DHT. This is done to make validation possible in situations where DHT sharding could occur.
@@ -78,20 +80,35 @@
 extern crate lazy_static;
 
 use chrono::{DateTime, Utc};
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::time::Duration;
 
 use hdi::prelude::*;
 use hdk::prelude::*;
+use serde::de::DeserializeOwned;
 
 mod bfs;
 mod convertions;
 mod dfs;
+mod fetch;
 pub mod errors;
+/// Reports describing how a search walked the time tree
+pub mod explain;
 mod impl_utils;
 
 /// Public methods exposed by lib
 pub mod methods;
+/// Total orderings applied to search results
+pub mod ordering;
+mod prune;
+mod query;
+mod range;
 mod search;
+mod signal;
+mod subscribers;
+/// Cache of time tree paths which can be reused across searches
+pub mod snapshot;
 mod traits;
 mod utils;
 mod validation;
@@ -102,8 +119,11 @@ pub mod entries;
 /// Trait to impl on entries that you want to add to time index
 pub use traits::IndexableEntry;
 
-use entries::{Index, IndexType};
+use entries::{Index, IndexRoot, IndexType, StringIndex, TimeIndex};
 use errors::{IndexError, IndexResult};
+use explain::QueryPlan;
+use query::QueryContext;
+use snapshot::TimeTreeSnapshot;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EntryChunkIndex {
@@ -111,58 +131,386 @@ pub struct EntryChunkIndex {
     pub links: Vec<Link>,
 }
 
+/// Index links created and deleted in a chunk. Created links include those which have since been deleted
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EntryChunkChanges {
+    pub index: Index,
+    pub created: Vec<Link>,
+    pub deleted: Vec<DeletedLink>,
+}
+
+/// Index link which has been deleted along with the action that deleted it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeletedLink {
+    pub link: Link,
+    pub delete_link_hash: ActionHash,
+    pub deleted_at: Timestamp,
+}
+
+/// Signal sent to other agents when an entry is indexed with [`index_entry_with_signal()`]
+#[derive(Serialize, Deserialize, Debug, Clone, SerializedBytes)]
+pub struct IndexSignal {
+    pub index: String,
+    /// Chunk the entry was indexed in
+    pub chunk: Index,
+    /// The indexed entry
+    pub target: AnyLinkableHash,
+    /// Tag of the index link
+    pub tag: LinkTag,
+}
+
+/// Agents an [`IndexSignal`] is sent to. The indexing agent is never sent its own signal
+pub enum SignalRecipients {
+    /// The supplied agents
+    Agents(Vec<AgentPubKey>),
+    /// Agents who created other index links in the same chunk which have not been deleted
+    ChunkAuthors,
+    /// Agents who have subscribed to the index within the given time to live, see [`subscribe()`]
+    Subscribers(Duration),
+}
+
+/// Kind of change recorded in an index's ingest log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+}
+
+/// Entry added to or removed from an index, as returned by [`get_changes_since()`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexChange {
+    pub kind: ChangeKind,
+    /// The indexed entry
+    pub target: AnyLinkableHash,
+    /// Time the change was recorded at. Can be used as the checkpoint for the next call to [`get_changes_since()`]
+    pub timestamp: Timestamp,
+}
+
+/// Entry loaded from an index link along with the link itself, the chunk it was found in and the action that created the entry
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexedItem<T> {
+    pub link: Link,
+    pub index: Index,
+    pub action: SignedActionHashed,
+    pub entry: T,
+}
+
+/// Links whose target could not be loaded during a lenient query, alongside the error which caused them to be skipped
+pub type SkippedLinks = Vec<(Link, IndexError)>;
+
 /// Configuration object that should be set in your host DNA's properties
 #[derive(Serialize, Deserialize, Debug, SerializedBytes)]
 pub struct IndexConfiguration {
     pub enforce_spam_limit: usize,
     pub max_chunk_interval: usize,
+    /// How far in milliseconds a chunk may start ahead of the author's clock. Defaults to 0 when not set
+    #[serde(default)]
+    pub max_future_skew_ms: usize,
+    /// Record each entry added to or removed from an index in the index's ingest log so that [`get_changes_since()`] can find
+    /// them. Entries are recorded by [`index_entry()`] and removals by [`remove_index_and_log()`], [`remove_author_links()`] and
+    /// [`prune_before()`]. Defaults to false when not set
+    #[serde(default)]
+    pub ingest_log: bool,
+}
+
+/// Options applied to the host calls made by a search
+#[derive(Debug, Clone)]
+pub struct QueryOptions {
+    /// Options used when getting the entries that index links point to. [`GetOptions::content()`] lets a UI render from data
+    /// already held locally and only go to the network for entries it does not have, before refreshing with [`GetOptions::latest()`].
+    /// The HDK does not accept options for get_links so links and time paths are always fetched using the host's default behaviour
+    pub get_options: GetOptions,
+    /// Upper bound on the work done by the search. Searches which run out of budget return the results found so far along with
+    /// a continuation, or fail with [`IndexError::BudgetExhausted`] if they ran out before reading any chunk. None lets the search
+    /// run until it has covered the whole time span
+    pub budget: Option<QueryBudget>,
+    /// Snapshot of the time tree consulted before fetching the children of a path. Children fetched by the search are added to it
+    pub snapshot: Option<Rc<RefCell<TimeTreeSnapshot>>>,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        QueryOptions {
+            get_options: GetOptions::latest(),
+            budget: None,
+            snapshot: None,
+        }
+    }
 }
 
+impl QueryOptions {
+    /// Query options which get the latest version of entries from the network. This is the default
+    pub fn latest() -> Self {
+        QueryOptions::default()
+    }
+
+    /// Query options which get entries from the local store when possible and only go to the network for entries not held locally.
+    /// Links and time paths are still fetched with the host's default behaviour, see [`QueryOptions::get_options`]
+    pub fn content() -> Self {
+        QueryOptions {
+            get_options: GetOptions::content(),
+            ..QueryOptions::default()
+        }
+    }
+
+    /// Limit the work done by searches using these options
+    pub fn with_budget(mut self, budget: QueryBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Reuse and extend snapshot in searches using these options
+    pub fn with_snapshot(mut self, snapshot: Rc<RefCell<TimeTreeSnapshot>>) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+}
+
+/// Limits on the work a search may do before returning. Each batched get_links or get host call counts as one host call
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryBudget {
+    pub max_host_calls: Option<usize>,
+    pub max_chunks: Option<usize>,
+}
+
+/// Results of a search. When the search stopped because its [`QueryBudget`] ran out, continuation is set to the time the search
+/// should be repeated from to get the rest of the results, see [`TimeRange::resume_from()`]. A budget which runs out while walking
+/// the time tree, before any chunk was read, fails the search with [`IndexError::BudgetExhausted`] instead, as repeating it would
+/// stop at the same point. Results are only ordered
+/// within a page and continuation is not set when the search already found limit results
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Page<T> {
+    pub results: Vec<T>,
+    pub continuation: Option<DateTime<Utc>>,
+}
+
+impl<T> Default for Page<T> {
+    fn default() -> Self {
+        Page {
+            results: vec![],
+            continuation: None,
+        }
+    }
+}
+
+impl<T> Page<T> {
+    /// Convert each of the results while keeping the continuation
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            results: self.results.into_iter().map(f).collect(),
+            continuation: self.continuation,
+        }
+    }
+}
+
+/// Summary of the links removed by a call to [`prune_before()`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PruneReport {
+    /// Chunks the calling agent's index links were removed from
+    pub chunks: Vec<Index>,
+    /// Number of links from chunks to indexed entries that were deleted
+    pub index_links: usize,
+    /// Number of time_path links from indexed entries back to chunks that were deleted
+    pub time_path_links: usize,
+    /// Time tree paths which were unlinked from their parent as nothing was left below them
+    pub paths: Vec<Path>,
+    /// False if there are more chunks before the cutoff left to remove, in which case prune_before should be called again
+    pub complete: bool,
+    /// Time the next call to prune_before should resume walking the tree from. None once complete
+    pub continuation: Option<DateTime<Utc>>,
+}
+
+/// Number of links removed from a chunk by a call to [`remove_author_links()`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChunkRemovals {
+    pub index: Index,
+    /// Number of links from the chunk to indexed entries that were deleted
+    pub index_links: usize,
+    /// Number of time_path links from indexed entries back to the chunk that were deleted
+    pub time_path_links: usize,
+}
+
+/// Strategy used to walk the time tree when loading entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SearchStrategy {
+    /// Descend one branch at a time in the order of the search. Best when only a few results are needed from a wide time span
     Dfs,
+    /// Fetch each level of the tree in full before descending. Best when all results in the time span are needed
     Bfs,
+    /// Pick Dfs or Bfs from the width of the time span, the limit and the sort key. A Dfs search started this way fetches the
+    /// rest of its frontier level by level once it gets too large for the number of results still needed
+    Auto,
+}
+
+/// Value used to order search results and to check them against the from & until bounds of a search.
+/// The time tree itself is always keyed by [`IndexableEntry::entry_time()`], so the key only affects which of the links found in the
+/// searched chunks are returned and in what order
+#[derive(Clone)]
+pub enum SortKey {
+    /// Use [`IndexableEntry::entry_time()`] of the loaded entry. Searches which do not load entries use the start of the chunk the link was found in
+    EntryTime,
+    /// Use the time the index link was created
+    LinkCreated,
+    /// Use a time extracted from the index link's tag. Links for which no time can be extracted are not returned
+    Custom(TagTime),
+}
+
+/// Extracts the time used by [`SortKey::Custom`] from the tag of an index link
+pub type TagTime = Rc<dyn Fn(&LinkTag) -> Option<DateTime<Utc>>>;
+
+/// Filter applied to the tags of index links when reading them from the time tree.
+/// `get_links` only supports prefix matching; all other variants are applied to the returned links after the
+/// longest prefix which can be derived from the filter has been used to narrow the DHT query
+#[derive(Clone)]
+pub enum LinkTagFilter {
+    /// Return all links regardless of their tag
+    All,
+    /// Return links whose tag is exactly equal to the supplied tag
+    Exact(LinkTag),
+    /// Return links whose tag starts with the supplied bytes
+    Prefix(LinkTag),
+    /// Return links which match any of the supplied filters
+    AnyOf(Vec<LinkTagFilter>),
+    /// Return links for which the supplied closure returns true
+    Predicate(Rc<dyn Fn(&LinkTag) -> bool>),
 }
 
-#[derive(Debug)]
-pub(crate) enum Order {
+/// Direction results are returned in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Order {
+    /// Newest first
     Desc,
+    /// Oldest first
     Asc,
 }
 
+/// End of a [`TimeRange`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Bound {
+    Included(DateTime<Utc>),
+    Excluded(DateTime<Utc>),
+    /// The range is not limited at this end. Searches resolve it to the start of the oldest or end of the newest indexed chunk
+    Unbounded,
+}
+
+/// Span of time searched by the search functions along with the order results are returned in. A range containing no time, such
+/// as one excluding both ends of a single instant, is searched like a range with nothing indexed in it: searches return an empty
+/// page without a continuation rather than failing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub earliest: Bound,
+    pub latest: Bound,
+    pub order: Order,
+}
+
+/// Search of an index: the range searched along with which of the index links found are returned and in what order.
+/// strategy is only used by searches which load entries, the others always fetch the time tree level by level
+#[derive(Clone)]
+pub struct IndexQuery {
+    pub range: TimeRange,
+    pub link_tag: LinkTagFilter,
+    /// Key results are ordered and bounded by. When None, searches returning links are ordered by [`SortKey::LinkCreated`] but
+    /// return every link in the chunks in range, as they are with [`SortKey::EntryTime`], so that links created long after the time
+    /// they were indexed under are still found. Searches loading entries use [`SortKey::EntryTime`]
+    pub sort_key: Option<SortKey>,
+    pub strategy: SearchStrategy,
+    pub limit: Option<usize>,
+}
+
+impl IndexQuery {
+    /// Query returning every link in range in the default order of the search, with the search strategy picked automatically
+    pub fn new(range: TimeRange) -> Self {
+        IndexQuery {
+            range,
+            link_tag: LinkTagFilter::All,
+            sort_key: None,
+            strategy: SearchStrategy::Auto,
+            limit: None,
+        }
+    }
+
+    /// Only return links whose tag matches link_tag
+    pub fn with_tag(mut self, link_tag: LinkTagFilter) -> Self {
+        self.link_tag = link_tag;
+        self
+    }
+
+    /// Order and bound results by sort_key
+    pub fn with_sort_key(mut self, sort_key: SortKey) -> Self {
+        self.sort_key = Some(sort_key);
+        self
+    }
+
+    /// Key used to order the results of searches returning links
+    pub(crate) fn link_sort_key(&self) -> SortKey {
+        self.sort_key.clone().unwrap_or(SortKey::LinkCreated)
+    }
+
+    /// Key used to check the links found by searches returning links against the range
+    pub(crate) fn link_bound_key(&self) -> SortKey {
+        self.sort_key.clone().unwrap_or(SortKey::EntryTime)
+    }
+
+    /// Key used by searches loading entries
+    pub(crate) fn item_sort_key(&self) -> SortKey {
+        self.sort_key.clone().unwrap_or(SortKey::EntryTime)
+    }
+
+    /// Walk the time tree using strategy
+    pub fn with_strategy(mut self, strategy: SearchStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Return at most limit results
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
 /// Gets all links with optional tag link_tag since last_seen time with option to limit number of results by limit
-/// Note: if last_seen is a long time ago in a popular DHT then its likely this function will take a very long time to run
+/// Note: if last_seen is a long time ago in a popular DHT then its likely this function will take a very long time to run.
+/// A [`QueryBudget`] can be set in options to bound the work done and continue from the returned page's continuation instead.
+/// Only the range and link_tag of query are used; chunks are always returned newest first
 /// TODO: would be cool to support DFS and BFS here
 pub fn get_indexes_for_time_span<PLT: Clone>(
     index: String,
-    from: DateTime<Utc>,
-    until: DateTime<Utc>,
-    link_tag: Option<LinkTag>,
+    query: IndexQuery,
+    options: QueryOptions,
     index_link_type: impl LinkTypeFilterExt + Clone,
     path_link_type: PLT
-) -> IndexResult<Vec<EntryChunkIndex>> 
+) -> IndexResult<Page<EntryChunkIndex>> 
     where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
-    //Check that timeframe specified is greater than the INDEX_DEPTH.
-    if until.timestamp_millis() - from.timestamp_millis() < MAX_CHUNK_INTERVAL.as_millis() as i64 {
-        return Err(IndexError::RequestError(
-            "Time frame is smaller than index interval",
-        ));
-    };
+    methods::get_indexes_for_time_span(
+        query.range, index, query.link_tag, &mut QueryContext::new(options), index_link_type, path_link_type
+    )
+}
 
-    Ok(methods::get_indexes_for_time_span(
-        from, until, index, link_tag, index_link_type, path_link_type
-    )?)
+/// Gets the index links created and deleted in each chunk inside of the query's range, including links which are no longer returned
+/// by get_links. Clients syncing a copy of the index can apply these as deltas. Only the range and link_tag of query are used;
+/// chunks are returned in the order of the range while the changes inside each chunk are always in the order they were made
+pub fn get_index_changes_for_time_span<PLT: Clone>(
+    index: String,
+    query: IndexQuery,
+    options: QueryOptions,
+    index_link_type: impl LinkTypeFilterExt + Clone,
+    path_link_type: PLT
+) -> IndexResult<Page<EntryChunkChanges>> 
+    where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
+    methods::get_index_changes_for_time_span(
+        query.range, index, query.link_tag, &mut QueryContext::new(options), index_link_type, path_link_type
+    )
 }
 
-/// Get links for index that exist between two timestamps
+/// Get links for index that exist inside of the query's range
 pub fn get_links_for_time_span<PLT: Clone>(
     index: String,
-    from: DateTime<Utc>,
-    until: DateTime<Utc>,
-    link_tag: Option<LinkTag>,
-    limit: Option<usize>,
+    query: IndexQuery,
+    options: QueryOptions,
     index_link_type: impl LinkTypeFilterExt + Clone,
     path_link_type: PLT
-) -> IndexResult<Vec<Link>> 
+) -> IndexResult<Page<Link>> 
     where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
     // //Check that timeframe specified is greater than the INDEX_DEPTH.
     // if until.timestamp_millis() - from.timestamp_millis() < MAX_CHUNK_INTERVAL.as_millis() as i64 {
@@ -171,26 +519,49 @@ pub fn get_links_for_time_span<PLT: Clone>(
     //     ));
     // };
 
-    Ok(methods::get_links_for_time_span(
-        index, from, until, link_tag, limit, index_link_type, path_link_type
-    )?)
+    methods::get_links_for_time_span(
+        IndexRoot::Index(index), query, &mut QueryContext::new(options), index_link_type, path_link_type
+    )
 }
 
-/// Get links for index that exist between two timestamps and attempt to serialize link targets to T
+/// Get links for index that exist inside of the query's range and decode each link's tag into Tag.
+/// Links must have been indexed with a tag created by [`encode_link_tag()`]
+pub fn get_tagged_links_for_time_span<Tag: DeserializeOwned + std::fmt::Debug, PLT: Clone>(
+    index: String,
+    query: IndexQuery,
+    options: QueryOptions,
+    index_link_type: impl LinkTypeFilterExt + Clone,
+    path_link_type: PLT
+) -> IndexResult<Page<(Link, Tag)>>
+    where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
+    let page = methods::get_links_for_time_span(
+        IndexRoot::Index(index), query, &mut QueryContext::new(options), index_link_type, path_link_type
+    )?;
+    Ok(Page {
+        results: page
+            .results
+            .into_iter()
+            .map(|link| {
+                let tag = decode_link_tag::<Tag>(&link.tag)?;
+                Ok((link, tag))
+            })
+            .collect::<IndexResult<Vec<(Link, Tag)>>>()?,
+        continuation: page.continuation,
+    })
+}
+
+/// Get links for index that exist inside of the query's range and attempt to serialize link targets to T
 pub fn get_links_and_load_for_time_span<
     T: TryFrom<SerializedBytes, Error = SerializedBytesError> + IndexableEntry + std::fmt::Debug,
     ILT: LinkTypeFilterExt + Clone,
     PLT: Clone
 >(
     index: String,
-    from: DateTime<Utc>,
-    until: DateTime<Utc>,
-    link_tag: Option<LinkTag>,
-    strategy: SearchStrategy,
-    limit: Option<usize>,
+    query: IndexQuery,
+    options: QueryOptions,
     index_link_type: ILT,
     path_link_type: PLT
-) -> IndexResult<Vec<T>> 
+) -> IndexResult<Page<T>> 
     where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
     // //Check that timeframe specified is greater than the INDEX_DEPTH.
     // if until.timestamp_millis() - from.timestamp_millis() < MAX_CHUNK_INTERVAL.as_millis() as i64 {
@@ -199,34 +570,152 @@ pub fn get_links_and_load_for_time_span<
     //     ));
     // };
 
-    Ok(methods::get_links_and_load_for_time_span::<T, ILT, PLT>(
-        from, until, index, link_tag, strategy, limit, index_link_type, path_link_type
-    )?)
+    Ok(methods::get_indexed_items_for_time_span::<T, ILT, PLT>(
+        index, query, &mut QueryContext::new(options), index_link_type, path_link_type, None
+    )?
+    .map(|item| item.entry))
+}
+
+/// Get links for index that exist inside of the query's range and load their targets into T. Each result also contains the link,
+/// the chunk it was found in and the action which created the entry
+pub fn get_indexed_items_for_time_span<
+    T: TryFrom<SerializedBytes, Error = SerializedBytesError> + IndexableEntry + std::fmt::Debug,
+    ILT: LinkTypeFilterExt + Clone,
+    PLT: Clone
+>(
+    index: String,
+    query: IndexQuery,
+    options: QueryOptions,
+    index_link_type: ILT,
+    path_link_type: PLT
+) -> IndexResult<Page<IndexedItem<T>>>
+    where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
+    methods::get_indexed_items_for_time_span::<T, ILT, PLT>(
+        index, query, &mut QueryContext::new(options), index_link_type, path_link_type, None
+    )
+}
+
+/// Same as [`get_indexed_items_for_time_span()`] but links whose target cannot be loaded or decoded into T are skipped instead
+/// of failing the whole query. Skipped links are returned next to the loaded items alongside the error that caused them to be skipped.
+/// Links whose target could not be found are also returned as skipped, where [`get_indexed_items_for_time_span()`] leaves them out
+pub fn get_indexed_items_for_time_span_lenient<
+    T: TryFrom<SerializedBytes, Error = SerializedBytesError> + IndexableEntry + std::fmt::Debug,
+    ILT: LinkTypeFilterExt + Clone,
+    PLT: Clone
+>(
+    index: String,
+    query: IndexQuery,
+    options: QueryOptions,
+    index_link_type: ILT,
+    path_link_type: PLT
+) -> IndexResult<(Page<IndexedItem<T>>, SkippedLinks)>
+    where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
+    let mut skipped = vec![];
+    let items = methods::get_indexed_items_for_time_span::<T, ILT, PLT>(
+        index, query, &mut QueryContext::new(options), index_link_type, path_link_type, Some(&mut skipped)
+    )?;
+    Ok((items, skipped))
+}
+
+/// Same as [`get_links_for_time_span()`] but also returns a [`QueryPlan`] describing how the time tree was searched
+pub fn explain_links_for_time_span<PLT: Clone>(
+    index: String,
+    query: IndexQuery,
+    options: QueryOptions,
+    index_link_type: impl LinkTypeFilterExt + Clone,
+    path_link_type: PLT
+) -> IndexResult<(Page<Link>, QueryPlan)>
+    where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
+    let mut ctx = QueryContext::explain(options);
+    let page = methods::get_links_for_time_span(
+        IndexRoot::Index(index), query, &mut ctx, index_link_type, path_link_type
+    )?;
+    Ok((page, ctx.plan.unwrap_or_default()))
+}
+
+/// Same as [`get_indexed_items_for_time_span()`] but also returns a [`QueryPlan`] describing how the time tree was searched
+pub fn explain_indexed_items_for_time_span<
+    T: TryFrom<SerializedBytes, Error = SerializedBytesError> + IndexableEntry + std::fmt::Debug,
+    ILT: LinkTypeFilterExt + Clone,
+    PLT: Clone
+>(
+    index: String,
+    query: IndexQuery,
+    options: QueryOptions,
+    index_link_type: ILT,
+    path_link_type: PLT
+) -> IndexResult<(Page<IndexedItem<T>>, QueryPlan)>
+    where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
+    let mut ctx = QueryContext::explain(options);
+    let page = methods::get_indexed_items_for_time_span::<T, ILT, PLT>(
+        index, query, &mut ctx, index_link_type, path_link_type, None
+    )?;
+    Ok((page, ctx.plan.unwrap_or_default()))
 }
 
 /// Uses sys_time to get links on current time index. Note: this is not guaranteed to return results. It will only look
 /// at the current time index which will cover as much time as the current system time - MAX_CHUNK_INTERVAL
 pub fn get_current_index<PLT: Clone + LinkTypeFilterExt>(
     index: String,
-    link_tag: Option<LinkTag>,
+    link_tag: impl Into<LinkTagFilter>,
     path_link_type: PLT
 ) -> IndexResult<Option<EntryChunkIndex>> 
     where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
     match methods::get_current_index(index, path_link_type.clone())? {
         Some(index) => {
-            let links = get_links(index.path_entry_hash()?, path_link_type, link_tag)?;
+            let links = fetch::get_filtered_links(index.path_entry_hash()?, path_link_type, &link_tag.into())?;
             Ok(Some(EntryChunkIndex {
                 index: Index::try_from(index)?,
-                links: links,
+                links,
             }))
         }
         None => Ok(None),
     }
 }   
 
+/// Gets the earliest and latest chunks of the index by following the oldest and newest paths down the time tree. Chunks starting more
+/// than [`struct@MAX_FUTURE_SKEW`] after sys_time are ignored. Returns None if nothing has been indexed
+pub fn get_index_bounds<PLT: Clone>(
+    index: String,
+    path_link_type: PLT
+) -> IndexResult<Option<(Index, Index)>>
+    where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
+    methods::get_index_bounds(index, &mut QueryContext::default(), path_link_type)
+}
+
+/// Validation rule for links created with the path_link_type, to be called from an integrity zome's validate callback. Links to time
+/// chunks starting more than [`struct@MAX_FUTURE_SKEW`] after the link's own timestamp are invalid. This only checks that the chunk is
+/// consistent with the time the author claims to have created the link at: an author whose clock runs ahead also timestamps the link
+/// ahead and passes. Searches therefore also ignore chunks starting more than MAX_FUTURE_SKEW after the searching agent's sys_time.
+/// Links between other time paths are valid as long as their tag decodes to a time path component; links whose tag does not are
+/// invalid, as their target could be a chunk this rule would otherwise have rejected
+pub fn validate_chunk_link(create_link: &CreateLink) -> IndexResult<ValidateCallbackResult> {
+    let component = match hdk::hash_path::path::Component::try_from(SerializedBytes::from(UnsafeBytes::from(create_link.tag.0.clone()))) {
+        Ok(component) => component,
+        Err(_error) => return Ok(ValidateCallbackResult::Invalid(String::from("Path link tag is not a path component"))),
+    };
+    if let Ok(chunk) = Index::try_from(component.clone()) {
+        return match chunk.validate_chunk_time(create_link.timestamp, *MAX_FUTURE_SKEW) {
+            Ok(()) => Ok(ValidateCallbackResult::Valid),
+            Err(IndexError::RequestError(reason)) => Ok(ValidateCallbackResult::Invalid(reason.to_string())),
+            Err(error) => Err(error),
+        };
+    };
+    let bytes = || SerializedBytes::from(UnsafeBytes::from(<Vec<u8>>::from(component.clone())));
+    if TimeIndex::try_from(component.clone()).is_ok()
+        || StringIndex::try_from(component.clone()).is_ok()
+        || IndexRoot::try_from(bytes()).is_ok()
+    {
+        Ok(ValidateCallbackResult::Valid)
+    } else {
+        Ok(ValidateCallbackResult::Invalid(String::from("Path link tag is not a time path component")))
+    }
+}
+
 /// Index a given entry. Uses ['IndexableEntry::entry_time()'] to get time it should be indexed under.
-/// Will create link from time path to entry with link_tag passed into fn
-pub fn index_entry<T: IndexableEntry, LT: Into<LinkTag>, ILT: Clone, PLT>(
+/// Will create link from time path to entry with link_tag passed into fn. Structured tag data can be attached by
+/// passing a tag created with [`encode_link_tag()`]
+pub fn index_entry<T: IndexableEntry, LT: Into<LinkTag>, ILT: Clone, PLT: Clone>(
     index: String,
     data: T,
     link_tag: LT,
@@ -234,36 +723,185 @@ pub fn index_entry<T: IndexableEntry, LT: Into<LinkTag>, ILT: Clone, PLT>(
     path_link_type: PLT
 ) -> IndexResult<()> 
     where ScopedLinkType: TryFrom<ILT, Error = WasmError> + TryFrom<PLT, Error = WasmError> {
-    let index = methods::create_for_timestamp(index, data.entry_time(), path_link_type)?;
-    //Create link from end of time path to entry that should be indexed
-    create_link(index.path_entry_hash()?, data.hash()?, index_link_type.clone(), link_tag)?;
-    //Create link from entry that should be indexed back to time tree so tree links can be found when starting from entry
-    create_link(data.hash()?, index.path_entry_hash()?, index_link_type, LinkTag::new("time_path"))?;
+    methods::index_entry(index, &data, link_tag.into(), index_link_type, path_link_type)?;
     Ok(())
 }
 
-/// Removes a given indexed entry from the time tree
-pub fn remove_index(indexed_entry: EntryHash, index_link_type: impl LinkTypeFilterExt + Clone) -> IndexResult<()> {
-    let time_paths =
-        get_links(indexed_entry.clone(), index_link_type.clone(), Some(LinkTag::new("time_path")))?;
-    for time_path in time_paths {
-        let path_links = get_links(time_path.target.clone(), index_link_type.clone(), None)?;
-        let path_links: Vec<Link> = path_links
-            .into_iter()
-            .filter(|link| EntryHash::from(link.target.to_owned()) == indexed_entry)
-            .collect();
-        for path_link in path_links {
-            // debug!(
-            //     "Deleting link: {:#?}",
-            //     path_link.create_link_hash.to_owned()
-            // );
-            delete_link(path_link.create_link_hash.to_owned())?;
-        }
-    }
+/// Index a given entry in the same way as [`index_entry()`] and then send an [`IndexSignal`] describing the new index link to
+/// recipients with remote_signal. Recipients receive the signal in their `recv_remote_signal` callback and can read it with
+/// [`decode_index_signal()`]. Signals are best effort: if the recipients cannot be found or the signal cannot be sent the error is
+/// logged and the entry stays indexed
+pub fn index_entry_with_signal<T: IndexableEntry, LT: Into<LinkTag>, ILT: LinkTypeFilterExt + Clone, PLT: Clone>(
+    index: String,
+    data: T,
+    link_tag: LT,
+    recipients: SignalRecipients,
+    index_link_type: ILT,
+    path_link_type: PLT
+) -> IndexResult<()> 
+    where ScopedLinkType: TryFrom<ILT, Error = WasmError> + TryFrom<PLT, Error = WasmError> {
+    let link_tag = link_tag.into();
+    let chunk_path = methods::index_entry(
+        index.clone(),
+        &data,
+        link_tag.clone(),
+        index_link_type.clone(),
+        path_link_type.clone()
+    )?;
+    //Failing to signal should not fail the call and roll back the links which were just created
+    let signalled = (|| {
+        let index_signal = IndexSignal {
+            index,
+            chunk: Index::try_from(chunk_path.clone())?,
+            target: data.hash()?.into(),
+            tag: link_tag,
+        };
+        signal::send_index_signal(
+            index_signal,
+            recipients,
+            &chunk_path,
+            &mut QueryContext::default(),
+            index_link_type,
+            path_link_type
+        )
+    })();
+    if let Err(error) = signalled {
+        warn!("Could not send index signal: {}", error);
+    };
+    Ok(())
+}
+
+/// Decode a signal received in a `recv_remote_signal` callback into the [`IndexSignal`] sent by [`index_entry_with_signal()`].
+/// Fails if the signal is of any other type
+pub fn decode_index_signal(signal: SerializedBytes) -> IndexResult<IndexSignal> {
+    Ok(IndexSignal::try_from(signal)?)
+}
+
+/// Serialize a tag payload into a LinkTag that can be used when indexing an entry. This allows data such as a title or
+/// content type to be read from the index links without having to get the linked entry
+pub fn encode_link_tag<Tag: Serialize + std::fmt::Debug>(tag: &Tag) -> IndexResult<LinkTag> {
+    Ok(LinkTag::new(encode(tag)?))
+}
 
+/// Decode a LinkTag created by [`encode_link_tag()`] back into its payload type
+pub fn decode_link_tag<Tag: DeserializeOwned + std::fmt::Debug>(tag: &LinkTag) -> IndexResult<Tag> {
+    Ok(decode(tag.as_ref())?)
+}
+
+/// Removes a given indexed entry from the time tree. Only index links whose tag matches link_tag will be removed.
+/// The removal is not recorded in the ingest log, use [`remove_index_and_log()`] for that
+pub fn remove_index(
+    indexed_entry: EntryHash,
+    link_tag: impl Into<LinkTagFilter>,
+    index_link_type: impl LinkTypeFilterExt + Clone
+) -> IndexResult<()> {
+    methods::remove_index_links(&indexed_entry, &link_tag.into(), index_link_type)?;
+    Ok(())
+}
+
+/// Removes a given indexed entry from the time tree of index as [`remove_index()`] does. When `ingest_log` is enabled and any index
+/// links were removed the removal is recorded in the ingest log of index
+pub fn remove_index_and_log<ILT: LinkTypeFilterExt + Clone, PLT>(
+    index: String,
+    indexed_entry: EntryHash,
+    link_tag: impl Into<LinkTagFilter>,
+    index_link_type: ILT,
+    path_link_type: PLT
+) -> IndexResult<()>
+    where ScopedLinkType: TryFrom<ILT, Error = WasmError> + TryFrom<PLT, Error = WasmError> {
+    if methods::remove_index_links(&indexed_entry, &link_tag.into(), index_link_type.clone())? && *INGEST_LOG {
+        methods::log_change(index, indexed_entry.into(), ChangeKind::Removed, index_link_type, path_link_type)?;
+    };
     Ok(())
 }
 
+/// Gets the entries added to or removed from index after since, oldest first, regardless of the time they were indexed under.
+/// Changes are only recorded when `ingest_log` is enabled in the host DNA's properties. The timestamp of the last change returned
+/// can be used as since for the next call
+pub fn get_changes_since<PLT: Clone>(
+    index: String,
+    since: DateTime<Utc>,
+    options: QueryOptions,
+    index_link_type: impl LinkTypeFilterExt + Clone,
+    path_link_type: PLT
+) -> IndexResult<Page<IndexChange>>
+    where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
+    methods::get_changes_since(index, since, &mut QueryContext::new(options), index_link_type, path_link_type)
+}
+
+/// Registers the calling agent as a subscriber of index. Registrations are filed in a time tree of their own under the time they
+/// were made, so they expire once they are older than the time to live readers pass to [`get_subscribers()`]. Subscribers should
+/// subscribe again within that time to stay registered. Subscribing again before the current chunk of registrations has ended does
+/// nothing
+pub fn subscribe<ILT: LinkTypeFilterExt + Clone, PLT>(
+    index: String,
+    index_link_type: ILT,
+    path_link_type: PLT
+) -> IndexResult<()> 
+    where ScopedLinkType: TryFrom<ILT, Error = WasmError> + TryFrom<PLT, Error = WasmError> {
+    subscribers::subscribe(index, index_link_type, path_link_type)
+}
+
+/// Removes the calling agent's registrations for index made within ttl, returning how many were removed
+pub fn unsubscribe<PLT: Clone>(
+    index: String,
+    ttl: Duration,
+    index_link_type: impl LinkTypeFilterExt,
+    path_link_type: PLT
+) -> IndexResult<usize> 
+    where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
+    subscribers::unsubscribe(index, ttl, &mut QueryContext::default(), index_link_type, path_link_type)
+}
+
+/// Gets the agents subscribed to index within ttl, most recently registered first. Like the searches above, a [`QueryBudget`] can be
+/// set in options to bound the chunks of registrations read; the returned page then holds the subscribers found so far and its
+/// continuation can be passed as resume_from to read the older registrations
+pub fn get_subscribers<PLT: Clone>(
+    index: String,
+    ttl: Duration,
+    resume_from: Option<DateTime<Utc>>,
+    options: QueryOptions,
+    index_link_type: impl LinkTypeFilterExt + Clone,
+    path_link_type: PLT
+) -> IndexResult<Page<AgentPubKey>> 
+    where ScopedLinkType: TryFrom<PLT, Error = WasmError> {
+    subscribers::get_subscribers(index, ttl, resume_from, &mut QueryContext::new(options), index_link_type, path_link_type)
+}
+
+/// Removes what the calling agent indexed in chunks which end at or before cutoff. Index links and the time_path links back to the
+/// chunk are deleted, then time tree paths left with nothing below them are unlinked. Links created by other agents are left in
+/// place, along with the paths leading to them. At most max_chunks chunks are removed per call, which must be at least one, so
+/// that long histories can be pruned across several calls; the returned report says if another call is needed and holds the
+/// continuation to pass as resume_from so that the next call does not walk the tree from the oldest chunk again. With
+/// `ingest_log` enabled the entries left without any index links are recorded as removed, as they are by [`remove_index_and_log()`].
+/// The budget of options bounds the host calls made both to walk the tree and to delete the links, and fewer chunks are removed
+/// when it does not allow max_chunks to be. If it runs out before one chunk is removed [`IndexError::BudgetExhausted`] is returned
+pub fn prune_before<ILT: LinkTypeFilterExt + Clone, PLT: Clone>(
+    index: String,
+    cutoff: DateTime<Utc>,
+    max_chunks: usize,
+    resume_from: Option<DateTime<Utc>>,
+    options: QueryOptions,
+    index_link_type: ILT,
+    path_link_type: PLT
+) -> IndexResult<PruneReport>
+    where ScopedLinkType: TryFrom<ILT, Error = WasmError> + TryFrom<PLT, Error = WasmError> {
+    prune::prune_before(index, cutoff, max_chunks, resume_from, &mut QueryContext::new(options), index_link_type, path_link_type)
+}
+
+/// Removes every entry the calling agent indexed inside of range. Unlike [`remove_index()`] the indexed entries do not need to be
+/// known up front; index links created by the agent are found by walking the chunks in range. Links created by other agents are
+/// left in place. Returns the number of links removed from each chunk
+pub fn remove_author_links<ILT: LinkTypeFilterExt + Clone, PLT: Clone>(
+    index: String,
+    range: TimeRange,
+    index_link_type: ILT,
+    path_link_type: PLT
+) -> IndexResult<Vec<ChunkRemovals>>
+    where ScopedLinkType: TryFrom<ILT, Error = WasmError> + TryFrom<PLT, Error = WasmError> {
+    prune::remove_author_links(index, range, &mut QueryContext::default(), index_link_type, path_link_type)
+}
+
 // Library configuration setup
 lazy_static! {
     //Point at which links are considered spam and linked expressions are not allowed
@@ -280,6 +918,20 @@ lazy_static! {
             .expect("Could not convert zome dna properties to IndexConfiguration. Please ensure that your dna properties contains a IndexConfiguration field.");
         Duration::from_millis(properties.max_chunk_interval as u64)
     };
+    //Tolerance for chunks starting ahead of the clock of the agent creating or validating them
+    pub static ref MAX_FUTURE_SKEW: Duration = {
+        let host_dna_config = dna_info().expect("Could not get zome configuration").properties;
+        let properties = IndexConfiguration::try_from(host_dna_config)
+            .expect("Could not convert zome dna properties to IndexConfiguration. Please ensure that your dna properties contains a IndexConfiguration field.");
+        Duration::from_millis(properties.max_future_skew_ms as u64)
+    };
+    //Whether changes to indexes are recorded in their ingest log
+    pub static ref INGEST_LOG: bool = {
+        let host_dna_config = dna_info().expect("Could not get zome configuration").properties;
+        let properties = IndexConfiguration::try_from(host_dna_config)
+            .expect("Could not convert zome dna properties to IndexConfiguration. Please ensure that your dna properties contains a IndexConfiguration field.");
+        properties.ingest_log
+    };
     //Determine what depth of time index should be hung from
     pub static ref INDEX_DEPTH: Vec<entries::IndexType> =
         if *MAX_CHUNK_INTERVAL < Duration::from_secs(1) {
@@ -1,15 +1,25 @@
 use std::time::Duration;
 
 use chrono::{DateTime, NaiveDateTime, Utc};
-use hdk::{hash_path::path::Component, prelude::*};
+use hdk::prelude::*;
 
-use crate::bfs::find_paths_for_time_span;
-use crate::dfs::methods::make_dfs_search;
-use crate::search::find_newest_time_path;
-use crate::utils::{add_time_index_to_path, get_index_for_timestamp, get_time_path};
+use crate::bfs::find_chunks_for_time_span;
+use crate::dfs::methods::{make_dfs_search, DfsSearch};
+use crate::ordering::{compare_chunks, compare_links, sort_indexed_items, sort_links, sort_links_by};
+use crate::query::{get_continuation, QueryContext};
+use crate::range::NormalizedRange;
+use crate::search::{choose_search_strategy, find_edge_chunk};
+use crate::fetch::{get_filtered_link_details_batch, get_filtered_links, get_filtered_links_batch, link_from_create, load_chunk_items, read_chunks};
+use crate::utils::{
+    add_time_index_to_path, get_datetime_for_timestamp, get_index_for_timestamp, get_latest_allowed_time, get_time_path,
+    is_link_within_bounds, is_within_bounds,
+};
 use crate::{
-    entries::{Index, IndexType, StringIndex, TimeIndex},
-    EntryChunkIndex, IndexableEntry, SearchStrategy, MAX_CHUNK_INTERVAL,
+    entries::{Index, IndexRoot, IndexType, TimeIndex},
+    decode_link_tag, encode_link_tag, Bound, ChangeKind, DeletedLink, EntryChunkChanges, EntryChunkIndex, IndexChange,
+    IndexQuery, IndexableEntry, IndexedItem, LinkTagFilter, Page, SearchStrategy, SkippedLinks,
+    TimeRange,
+    INGEST_LOG, MAX_CHUNK_INTERVAL, MAX_FUTURE_SKEW,
 };
 use crate::{
     errors::{IndexError, IndexResult},
@@ -18,7 +28,7 @@ use crate::{
 
 impl Index {
     /// Create a new time index
-    pub(crate) fn new<L>(&self, index: String, path_link_type: L) -> IndexResult<Path>
+    pub(crate) fn new<L>(&self, index: &IndexRoot, path_link_type: L) -> IndexResult<Path>
     where
         ScopedLinkType: TryFrom<L, Error = WasmError>,
     {
@@ -28,7 +38,7 @@ impl Index {
             .ok_or(IndexError::InternalError("Should not overflow"))?
             .to_std()
             .map_err(|_err| IndexError::InternalError("Should not overflow"))?;
-        if self.from > now_since_epoch {
+        if self.from > now_since_epoch + *MAX_FUTURE_SKEW {
             return Err(IndexError::RequestError(
                 "Time index cannot start in the future",
             ));
@@ -64,9 +74,7 @@ where
     let now = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(now.0, now.1), Utc);
 
     //Create current time path
-    let mut time_path = vec![Component::try_from(
-        StringIndex(index).get_sb()?.bytes().to_owned(),
-    )?];
+    let mut time_path = vec![IndexRoot::Index(index).component()?];
     add_time_index_to_path::<TimeIndex>(&mut time_path, &now, IndexType::Year)?;
     add_time_index_to_path::<TimeIndex>(&mut time_path, &now, IndexType::Month)?;
     add_time_index_to_path::<TimeIndex>(&mut time_path, &now, IndexType::Day)?;
@@ -96,289 +104,380 @@ where
     }
 }
 
-/// Traverses time tree following latest time links until it finds the latest index
-pub fn get_latest_index<PLT: Into<ScopedLinkType> + Clone>(
+/// Traverses time tree following latest time links until it finds the latest index. Branches without any indexes are skipped as are
+/// branches starting more than max_future_skew after sys_time, which can only have been created by agents with skewed clocks.
+/// Pass [`struct@crate::MAX_FUTURE_SKEW`] to allow the same skew as chunk validation
+pub fn get_latest_index<PLT: Clone>(
+    index: String,
+    max_future_skew: std::time::Duration,
+    path_link_type: PLT,
+) -> IndexResult<Option<Path>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let not_after = get_latest_allowed_time(max_future_skew)?;
+    get_edge_index(index, &Order::Desc, None::<LinkTypeFilter>, Some(not_after), path_link_type)
+}
+
+/// Traverses time tree following latest time links until it finds the latest index which still has index links of index_link_type.
+/// Indexes whose links have all been deleted are skipped along with branches starting more than max_future_skew after sys_time
+pub fn get_latest_index_with_links<ILT: LinkTypeFilterExt + Clone, PLT: Clone>(
     index: String,
+    index_link_type: ILT,
+    max_future_skew: std::time::Duration,
     path_link_type: PLT,
-) -> IndexResult<Option<Path>> {
+) -> IndexResult<Option<Path>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let not_after = get_latest_allowed_time(max_future_skew)?;
+    get_edge_index(index, &Order::Desc, Some(index_link_type), Some(not_after), path_link_type)
+}
+
+/// Traverses time tree following earliest time links until it finds the earliest index. Branches without any indexes are skipped
+pub fn get_earliest_index<PLT: Clone>(index: String, path_link_type: PLT) -> IndexResult<Option<Path>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    get_edge_index(index, &Order::Asc, None::<LinkTypeFilter>, None, path_link_type)
+}
+
+fn get_edge_index<ILT: LinkTypeFilterExt + Clone, PLT: Clone>(
+    index: String,
+    edge: &Order,
+    index_link_type: Option<ILT>,
+    not_after: Option<DateTime<Utc>>,
+    path_link_type: PLT,
+) -> IndexResult<Option<Path>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
     // This should also be smarter. We could at the least derive the index & current year and check that for paths before moving
     // to the previous year. This would help remove 2 get_link() calls from the DHT on source Index path & Index + Year path
-    let time_path = Path::from(vec![Component::from(
-        StringIndex(index).get_sb()?.bytes().to_owned(),
-    )]);
-    let time_path = find_newest_time_path::<TimeIndex, PLT>(
-        time_path,
-        IndexType::Year,
-        path_link_type.clone(),
-    )?;
-    let time_path = find_newest_time_path::<TimeIndex, PLT>(
-        time_path,
-        IndexType::Month,
-        path_link_type.clone(),
-    )?;
-    let time_path =
-        find_newest_time_path::<TimeIndex, PLT>(time_path, IndexType::Day, path_link_type.clone())?;
-    let time_path = find_newest_time_path::<TimeIndex, PLT>(
-        time_path,
-        IndexType::Hour,
-        path_link_type.clone(),
-    )?;
-    let time_path = find_newest_time_path::<TimeIndex, PLT>(
-        time_path,
-        IndexType::Minute,
-        path_link_type.clone(),
-    )?;
-    let time_path = find_newest_time_path::<TimeIndex, PLT>(
-        time_path,
-        IndexType::Second,
-        path_link_type.clone(),
-    )?;
+    let index = IndexRoot::Index(index);
+    //Paths are only made of links so the chunk path found is returned as it is rather than being loaded
+    let chunk = find_edge_chunk(&index, edge, index_link_type, not_after, path_link_type, &mut QueryContext::default())?;
+    Ok(chunk.map(|(chunk_path, _index)| chunk_path))
+}
 
-    let indexes = time_path
-        .typed(path_link_type)?
-        .children_paths()?
-        .into_iter()
-        .map(|val| val.path)
-        .collect::<Vec<_>>();
-    let ser_path = indexes
-        .clone()
-        .into_iter()
-        .map(|path| Ok(Index::try_from(path)?.from))
-        .collect::<IndexResult<Vec<Duration>>>()?;
-    let permutation = permutation::sort_by(&ser_path[..], |a, b| a.partial_cmp(&b).unwrap());
-    let mut ordered_indexes: Vec<Path> = permutation.apply_slice(&indexes[..]);
-    ordered_indexes.reverse();
+/// Get the earliest and latest chunks of the index, ignoring chunks starting more than MAX_FUTURE_SKEW after sys_time.
+/// Returns None if nothing has been indexed
+pub(crate) fn get_index_bounds<PLT: Clone>(
+    index: String,
+    ctx: &mut QueryContext,
+    path_link_type: PLT,
+) -> IndexResult<Option<(Index, Index)>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let index = IndexRoot::Index(index);
+    let not_after = Some(get_latest_allowed_time(*MAX_FUTURE_SKEW)?);
+    let earliest = find_edge_chunk(&index, &Order::Asc, None::<LinkTypeFilter>, not_after, path_link_type.clone(), ctx)?;
+    let latest = find_edge_chunk(&index, &Order::Desc, None::<LinkTypeFilter>, not_after, path_link_type, ctx)?;
+    Ok(earliest
+        .zip(latest)
+        .map(|((_earliest_path, earliest), (_latest_path, latest))| (earliest, latest)))
+}
 
-    //TODO: dont error out if cant find link target; just use next link
-    match ordered_indexes.pop() {
-        Some(link) => match get(link.path_entry_hash()?, GetOptions::latest())? {
-            Some(chunk) => Ok(Some(chunk.entry().to_app_option()?.ok_or(
-                IndexError::InternalError("Expected element to contain app entry data"),
-            )?)),
-            None => Err(IndexError::InternalError(
-                "Expected link target to contain point to an entry",
-            )),
-        },
-        None => Ok(None),
-    }
+/// Resolve range for a search of index. If there is nothing to search the empty page the search should return is given instead
+fn resolve_range<T, PLT: Clone>(
+    range: &TimeRange,
+    index: &IndexRoot,
+    ctx: &mut QueryContext,
+    path_link_type: PLT,
+) -> IndexResult<Result<NormalizedRange, Page<T>>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    Ok(range.resolve(index, path_link_type, ctx)?.ok_or_else(Page::default))
+}
+
+/// Find the chunks in range, in the order of the range, and keep as many of them as the budget allows to be read with one further
+/// host call. If not all of the chunks can be read the page continues from the time after the last chunk kept
+pub(crate) fn find_affordable_chunks<PLT: Clone>(
+    range: TimeRange,
+    index: IndexRoot,
+    ctx: &mut QueryContext,
+    path_link_type: PLT,
+) -> IndexResult<Page<(Path, Index)>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let range = match resolve_range(&range, &index, ctx, path_link_type.clone())? {
+        Ok(range) => range,
+        Err(page) => return Ok(page),
+    };
+    //Chunks are found in the order of the range so that a page cut short by the budget covers its start
+    let mut chunks = find_chunks_for_time_span(range.from(), range.until(), &index, &range.order, path_link_type, ctx)?;
+    //debug!("Got chunks after search: {:#?}", chunks);
+
+    let mut continuation = None;
+    let affordable = (0..=chunks.len())
+        .rev()
+        .find(|count| ctx.can_afford(1, *count))
+        .unwrap_or(0);
+    if affordable < chunks.len() {
+        chunks.truncate(affordable);
+        continuation = Some(get_continuation(chunks.last().map(|chunk| &chunk.1), &range.order)?);
+    };
+    ctx.record_chunks(&chunks)?;
+    Ok(Page {
+        results: chunks,
+        continuation,
+    })
 }
 
 /// Get all chunks that exist for some time period between from -> until
 pub(crate) fn get_indexes_for_time_span<PLT: Clone>(
-    from: DateTime<Utc>,
-    until: DateTime<Utc>,
+    range: TimeRange,
     index: String,
-    link_tag: Option<LinkTag>,
+    link_tag: LinkTagFilter,
+    ctx: &mut QueryContext,
     index_link_type: impl LinkTypeFilterExt + Clone,
     path_link_type: PLT,
-) -> IndexResult<Vec<EntryChunkIndex>>
+) -> IndexResult<Page<EntryChunkIndex>>
 where
     ScopedLinkType: TryFrom<PLT, Error = WasmError>,
 {
-    let paths = find_paths_for_time_span(from, until, index, path_link_type.clone())?;
-    //debug!("Got paths after search: {:#?}", paths);
-    let mut out: Vec<EntryChunkIndex> = vec![];
-
-    for path in paths {
-        let paths = path.typed(path_link_type.clone())?.children_paths()?;
-        let mut indexes = paths
-            .clone()
-            .into_iter()
-            .map(|path| {
-                let index = Index::try_from(path.path.clone())?;
-                let entry_chunk_index = EntryChunkIndex {
-                    index: index,
-                    links: get_links(
-                        path.path_entry_hash()?,
-                        index_link_type.clone(),
-                        link_tag.clone(),
-                    )?,
-                };
-                Ok(entry_chunk_index)
-            })
-            .collect::<IndexResult<Vec<EntryChunkIndex>>>()?;
-        out.append(&mut indexes);
+    //Only read as many chunks as the budget allows, links for all of them are fetched in one call
+    let Page {
+        results: chunks,
+        continuation,
+    } = find_affordable_chunks(range, IndexRoot::Index(index), ctx, path_link_type)?;
+    let bases = chunks
+        .iter()
+        .map(|(chunk_path, _index)| Ok(chunk_path.path_entry_hash()?.into()))
+        .collect::<IndexResult<Vec<AnyLinkableHash>>>()?;
+    let mut out = chunks
+        .into_iter()
+        .zip(get_filtered_links_batch(bases, index_link_type, &link_tag, ctx)?)
+        .map(|((_chunk_path, index), links)| EntryChunkIndex { links, index })
+        .collect::<Vec<EntryChunkIndex>>();
+    //Chunks are returned newest first with the links inside each chunk following the same direction
+    out.sort_by(|a, b| compare_chunks(b, a));
+    for chunk in out.iter_mut() {
+        sort_links(&mut chunk.links, &Order::Desc);
     }
-    //NOTE: untested logic
-    let timestamps = out
-        .clone()
+
+    Ok(Page {
+        results: out,
+        continuation,
+    })
+}
+
+/// Get the index links created and deleted in all chunks for some time period between from -> until
+pub(crate) fn get_index_changes_for_time_span<PLT: Clone>(
+    range: TimeRange,
+    index: String,
+    link_tag: LinkTagFilter,
+    ctx: &mut QueryContext,
+    index_link_type: impl LinkTypeFilterExt + Clone,
+    path_link_type: PLT,
+) -> IndexResult<Page<EntryChunkChanges>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    //Only read as many chunks as the budget allows, link details for all of them are fetched in one call
+    let Page {
+        results: chunks,
+        continuation,
+    } = find_affordable_chunks(range, IndexRoot::Index(index), ctx, path_link_type)?;
+    let bases = chunks
+        .iter()
+        .map(|(chunk_path, _index)| Ok(chunk_path.path_entry_hash()?.into()))
+        .collect::<IndexResult<Vec<AnyLinkableHash>>>()?;
+    let details = get_filtered_link_details_batch(bases, index_link_type, &link_tag, ctx)?;
+
+    //Chunks are returned in the order of the range with the changes inside each chunk in the order they were made
+    let out = chunks
         .into_iter()
-        .map(|val| val.index.from)
-        .collect::<Vec<Duration>>();
-    let permutation = permutation::sort_by(&timestamps[..], |a, b| a.partial_cmp(&b).unwrap());
-    let mut ordered_indexes: Vec<EntryChunkIndex> = permutation.apply_slice(&out[..]);
-    ordered_indexes.reverse();
+        .zip(details)
+        .map(|((_chunk_path, index), details)| {
+            let mut created = vec![];
+            let mut deleted = vec![];
+            for (create, deletes) in details {
+                let link = match link_from_create(&create) {
+                    Some(link) => link,
+                    None => continue,
+                };
+                //A link can be deleted more than once; the first delete is when it stopped being returned
+                if let Some(delete) = deletes.iter().min_by_key(|delete| delete.action().timestamp()) {
+                    deleted.push(DeletedLink {
+                        link: link.clone(),
+                        delete_link_hash: delete.action_address().clone(),
+                        deleted_at: delete.action().timestamp(),
+                    });
+                };
+                created.push(link);
+            }
+            sort_links(&mut created, &Order::Asc);
+            deleted.sort_by(|a, b| a.deleted_at.cmp(&b.deleted_at).then_with(|| compare_links(&a.link, &b.link)));
+            EntryChunkChanges {
+                index,
+                created,
+                deleted,
+            }
+        })
+        .collect::<Vec<EntryChunkChanges>>();
 
-    Ok(ordered_indexes)
+    Ok(Page {
+        results: out,
+        continuation,
+    })
 }
 
 /// Get all links that exist for some time period between from -> until
 pub(crate) fn get_links_for_time_span<PLT: Clone>(
-    index: String,
-    from: DateTime<Utc>,
-    until: DateTime<Utc>,
-    link_tag: Option<LinkTag>,
-    limit: Option<usize>,
+    index: IndexRoot,
+    query: IndexQuery,
+    ctx: &mut QueryContext,
     index_link_type: impl LinkTypeFilterExt + Clone,
     path_link_type: PLT,
-) -> IndexResult<Vec<Link>>
+) -> IndexResult<Page<Link>>
 where
     ScopedLinkType: TryFrom<PLT, Error = WasmError>,
 {
-    let order = if from > until {
-        Order::Desc
-    } else {
-        Order::Asc
+    let (sort_key, bound_key) = (query.link_sort_key(), query.link_bound_key());
+    let IndexQuery {
+        range,
+        link_tag,
+        limit,
+        ..
+    } = query;
+    let range = match resolve_range(&range, &index, ctx, path_link_type.clone())? {
+        Ok(range) => range,
+        Err(page) => return Ok(page),
     };
+    let (from, until, order) = (range.from(), range.until(), range.order);
 
-    let paths = match order {
-        Order::Desc => find_paths_for_time_span(until, from, index, path_link_type.clone())?,
-        Order::Asc => find_paths_for_time_span(from, until, index, path_link_type.clone())?,
-    };
+    let chunks = find_chunks_for_time_span(from, until, &index, &order, path_link_type, ctx)?;
+    //debug!("Got chunks after search: {:#?}", chunks);
 
-    if limit.is_some() {
-        debug!("hc_time_index::get_links_for_time_span: WARNING: Limit not supported on Bfs strategy. All links between bounds will be retrieved and returned");
-    };
-    //debug!("Got paths after search: {:#?}", paths);
-    let mut out: Vec<Link> = vec![];
-    for path in paths {
-        let paths = path.typed(path_link_type.clone())?.children_paths()?;
-        let mut indexes = paths
-            .clone()
-            .into_iter()
-            .map(|path| {
-                let links = get_links(
-                    path.path_entry_hash()?,
-                    index_link_type.clone(),
-                    link_tag.clone(),
-                )?;
-                Ok(links)
+    let mut out: Vec<(Link, Index)> = vec![];
+    let reads = read_chunks(&chunks, 1, limit, &sort_key, ctx, &mut out, |batch, ctx| {
+        ctx.record_chunks(batch)?;
+        let bases = batch
+            .iter()
+            .map(|(chunk_path, _index)| Ok(chunk_path.path_entry_hash()?.into()))
+            .collect::<IndexResult<Vec<AnyLinkableHash>>>()?;
+        let links = get_filtered_links_batch(bases, index_link_type.clone(), &link_tag, ctx)?;
+        Ok(batch
+            .iter()
+            .zip(links)
+            .flat_map(|((_chunk_path, index_chunk), links)| {
+                links
+                    .into_iter()
+                    .filter(|link| is_link_within_bounds(link, index_chunk, &bound_key, &from, &until, &order))
+                    .map(|link| (link, index_chunk.clone()))
+                    .collect::<Vec<(Link, Index)>>()
             })
-            .collect::<IndexResult<Vec<Vec<Link>>>>()?
-            .into_iter()
-            .flatten()
-            .collect();
-        out.append(&mut indexes);
-    }
-    //TODO: do sort based on path value
-    match order {
-        Order::Desc => {
-            out.sort_by(|a, b| b.timestamp.partial_cmp(&a.timestamp).unwrap());
-        }
-        Order::Asc => {
-            out.sort_by(|a, b| a.timestamp.partial_cmp(&b.timestamp).unwrap());
-        }
-    }
-    Ok(out)
+            .collect())
+    })?;
+    let continuation = reads
+        .exhausted
+        .then(|| get_continuation(reads.last_chunk.as_ref(), &order))
+        .transpose()?;
+    sort_links_by(&mut out, &sort_key, &order);
+    //The continuation is only set when chunks were left unread, which may still hold results to return
+    if let Some(limit) = limit {
+        out.truncate(limit);
+    };
+    Ok(Page {
+        results: out.into_iter().map(|(link, _index)| link).collect(),
+        continuation,
+    })
 }
 
-/// Get all links that exist for some time period between from -> until
-pub(crate) fn get_links_and_load_for_time_span<
+/// Get all links that exist for some time period between from -> until and load the entries they point to.
+/// If skipped is provided then links whose target cannot be loaded are added to it instead of failing the whole call
+pub(crate) fn get_indexed_items_for_time_span<
     T: TryFrom<SerializedBytes, Error = SerializedBytesError> + IndexableEntry + std::fmt::Debug,
     ILT: LinkTypeFilterExt + Clone,
     PLT: Clone,
 >(
-    from: DateTime<Utc>,
-    until: DateTime<Utc>,
     index: String,
-    link_tag: Option<LinkTag>,
-    strategy: SearchStrategy,
-    limit: Option<usize>,
+    query: IndexQuery,
+    ctx: &mut QueryContext,
     index_link_type: ILT,
     path_link_type: PLT,
-) -> IndexResult<Vec<T>>
+    mut skipped: Option<&mut SkippedLinks>,
+) -> IndexResult<Page<IndexedItem<T>>>
 where
     ScopedLinkType: TryFrom<PLT, Error = WasmError>,
 {
-    let order = if from > until {
-        Order::Desc
-    } else {
-        Order::Asc
+    let index = IndexRoot::Index(index);
+    let range = match resolve_range(&query.range, &index, ctx, path_link_type.clone())? {
+        Ok(range) => range,
+        Err(page) => return Ok(page),
     };
+    let (from, until, order) = (range.from(), range.until(), range.order.clone());
+    let (link_tag, sort_key, limit) = (&query.link_tag, &query.item_sort_key(), query.limit);
 
-    Ok(match strategy {
-        SearchStrategy::Bfs => {
-            let paths = match order {
-                Order::Desc => {
-                    find_paths_for_time_span(until, from, index, path_link_type.clone())?
-                }
-                Order::Asc => find_paths_for_time_span(from, until, index, path_link_type.clone())?,
-            };
-            let mut results: Vec<T> = vec![];
-
-            for path in paths {
-                let paths = path.typed(path_link_type.clone())?.children_paths()?;
-                let mut indexes = paths
-                    .clone()
-                    .into_iter()
-                    .map(|path_child| {
-                        let links = get_links(
-                            path_child.path_entry_hash()?,
-                            index_link_type.clone(),
-                            link_tag.clone(),
-                        )?;
-                        Ok(links)
-                    })
-                    .collect::<IndexResult<Vec<Vec<Link>>>>()?
+    //Auto searches which pick DFS are allowed to fall back to level by level fetching part way through. Level by level fetching
+    //cannot stop part way so it is not used when the search has a budget
+    let (strategy, adaptive) = match query.strategy {
+        SearchStrategy::Auto => (
+            choose_search_strategy(&from, &until, limit, sort_key, *MAX_CHUNK_INTERVAL),
+            !ctx.has_budget(),
+        ),
+        strategy => (strategy, false),
+    };
+
+    let Page {
+        mut results,
+        continuation,
+    } = match strategy {
+        SearchStrategy::Bfs | SearchStrategy::Auto => {
+            let chunks = find_chunks_for_time_span(from, until, &index, &order, path_link_type, ctx)?;
+
+            let mut results: Vec<IndexedItem<T>> = vec![];
+            let reads = read_chunks(&chunks, 2, limit, sort_key, ctx, &mut results, |batch, ctx| {
+                Ok(load_chunk_items::<T>(batch, index_link_type.clone(), link_tag, ctx, skipped.as_deref_mut())?
                     .into_iter()
-                    .flatten()
-                    .map(|link| {
-                        match get(
-                            link.target
-                                .into_entry_hash()
-                                .expect("Could not get entry hash for link target"),
-                            GetOptions::latest(),
-                        )? {
-                            Some(chunk) => Ok(Some(chunk.entry().to_app_option::<T>()?.ok_or(
-                                IndexError::InternalError(
-                                    "Expected element to contain app entry data",
-                                ),
-                            )?)),
-                            None => Ok(None),
-                        }
-                    })
-                    .filter_map(|val| {
-                        if val.is_ok() {
-                            let val = val.unwrap();
-                            if val.is_some() {
-                                Some(Ok(val.unwrap()))
-                            } else {
-                                None
-                            }
-                        } else {
-                            Some(Err(val.err().unwrap()))
-                        }
+                    .filter(|item| {
+                        sort_key
+                            .item_time(item)
+                            .map(|time| is_within_bounds(&time, &from, &until, &order))
+                            .unwrap_or(false)
                     })
-                    .collect::<IndexResult<Vec<T>>>()?;
-                results.append(&mut indexes);
+                    .collect())
+            })?;
+            Page {
+                results,
+                continuation: reads
+                    .exhausted
+                    .then(|| get_continuation(reads.last_chunk.as_ref(), &order))
+                    .transpose()?,
             }
-            match order {
-                Order::Desc => {
-                    results.sort_by(|a, b| b.entry_time().partial_cmp(&a.entry_time()).unwrap());
-                }
-                Order::Asc => {
-                    results.sort_by(|a, b| a.entry_time().partial_cmp(&b.entry_time()).unwrap());
-                }
-            }
-
-            results
         }
         SearchStrategy::Dfs => make_dfs_search::<T, ILT, PLT>(
-            index,
-            &from,
-            &until,
-            &order,
-            limit,
-            link_tag,
-            index_link_type,
-            path_link_type,
+            &DfsSearch {
+                index: &index,
+                range: &range,
+                query: &query,
+                index_link_type,
+                path_link_type,
+            },
+            adaptive,
+            ctx,
+            skipped,
         )?,
+    };
+
+    sort_indexed_items(&mut results, sort_key, &order);
+    //The continuation is only set when chunks were left unread, which may still hold results to return
+    if let Some(limit) = limit {
+        results.truncate(limit);
+    };
+    Ok(Page {
+        results,
+        continuation,
     })
 }
 
 /// Takes a timestamp and creates an index path
 pub(crate) fn create_for_timestamp<L>(
-    index: String,
+    index: &IndexRoot,
     time: DateTime<Utc>,
     path_link_type: L,
 ) -> IndexResult<Path>
@@ -389,3 +488,109 @@ where
     let path = time_index.new(index, path_link_type)?;
     Ok(path)
 }
+
+/// Link data into the chunk of index covering its entry time, along with a time_path link back from data to the chunk.
+/// Returns the path of the chunk
+pub(crate) fn index_entry<T: IndexableEntry, ILT: Clone, PLT: Clone>(
+    index: String,
+    data: &T,
+    link_tag: LinkTag,
+    index_link_type: ILT,
+    path_link_type: PLT,
+) -> IndexResult<Path>
+where
+    ScopedLinkType: TryFrom<ILT, Error = WasmError> + TryFrom<PLT, Error = WasmError>,
+{
+    let chunk = create_for_timestamp(&IndexRoot::Index(index.clone()), data.entry_time(), path_link_type.clone())?;
+    //Create link from end of time path to entry that should be indexed
+    create_link(chunk.path_entry_hash()?, data.hash()?, index_link_type.clone(), link_tag)?;
+    //Create link from entry that should be indexed back to time tree so tree links can be found when starting from entry
+    create_link(data.hash()?, chunk.path_entry_hash()?, index_link_type.clone(), LinkTag::new("time_path"))?;
+    if *INGEST_LOG {
+        log_change(index, data.hash()?.into(), ChangeKind::Added, index_link_type, path_link_type)?;
+    };
+    Ok(chunk)
+}
+
+/// Delete the index links to indexed_entry whose tag matches link_tag from each chunk it was indexed in. Returns true if any were deleted
+pub(crate) fn remove_index_links(
+    indexed_entry: &EntryHash,
+    link_tag: &LinkTagFilter,
+    index_link_type: impl LinkTypeFilterExt + Clone,
+) -> IndexResult<bool> {
+    let time_paths = get_links(indexed_entry.clone(), index_link_type.clone(), Some(LinkTag::new("time_path")))?;
+    let mut removed = false;
+    for time_path in time_paths {
+        let path_links = get_filtered_links(time_path.target.clone(), index_link_type.clone(), link_tag)?;
+        let path_links: Vec<Link> = path_links
+            .into_iter()
+            .filter(|link| EntryHash::from(link.target.to_owned()) == *indexed_entry)
+            .collect();
+        for path_link in path_links {
+            delete_link(path_link.create_link_hash.to_owned())?;
+            removed = true;
+        }
+    }
+    Ok(removed)
+}
+
+/// Record a change to index in its ingest log under the current time
+pub(crate) fn log_change<ILT, PLT>(
+    index: String,
+    target: AnyLinkableHash,
+    kind: ChangeKind,
+    index_link_type: ILT,
+    path_link_type: PLT,
+) -> IndexResult<()>
+where
+    ScopedLinkType: TryFrom<ILT, Error = WasmError> + TryFrom<PLT, Error = WasmError>,
+{
+    let now = get_datetime_for_timestamp(sys_time()?).ok_or(IndexError::InternalError("Should not overflow"))?;
+    let chunk = create_for_timestamp(&IndexRoot::IngestLog(index), now, path_link_type)?;
+    create_link(chunk.path_entry_hash()?, target, index_link_type, encode_link_tag(&kind)?)?;
+    Ok(())
+}
+
+/// Get the changes recorded in the ingest log of index after since, oldest first
+pub(crate) fn get_changes_since<PLT: Clone>(
+    index: String,
+    since: DateTime<Utc>,
+    ctx: &mut QueryContext,
+    index_link_type: impl LinkTypeFilterExt + Clone,
+    path_link_type: PLT,
+) -> IndexResult<Page<IndexChange>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    //Changes are filed under the time taken just before their link was created so a change made after since can be in the
+    //chunk before the one since falls in
+    let chunk_interval = chrono::Duration::from_std(*MAX_CHUNK_INTERVAL)
+        .map_err(|_err| IndexError::InternalError("Should not overflow"))?;
+    let range = TimeRange::between(Bound::Included(since - chunk_interval), Bound::Unbounded, Order::Asc);
+    let links = get_links_for_time_span(
+        IndexRoot::IngestLog(index),
+        IndexQuery::new(range),
+        ctx,
+        index_link_type,
+        path_link_type,
+    )?;
+    let Page {
+        results,
+        continuation,
+    } = links;
+    let changes = results
+        .into_iter()
+        .filter(|link| get_datetime_for_timestamp(link.timestamp).map(|time| time > since).unwrap_or(false))
+        .map(|link| {
+            Ok(IndexChange {
+                kind: decode_link_tag(&link.tag)?,
+                target: link.target,
+                timestamp: link.timestamp,
+            })
+        })
+        .collect::<IndexResult<Vec<IndexChange>>>()?;
+    Ok(Page {
+        results: changes,
+        continuation,
+    })
+}
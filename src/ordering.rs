@@ -0,0 +1,137 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Utc};
+use hdk::prelude::*;
+
+use crate::entries::Index;
+use crate::utils::{get_datetime_for_duration, get_datetime_for_timestamp};
+use crate::{EntryChunkIndex, IndexableEntry, IndexedItem, Order, SortKey};
+
+impl SortKey {
+    /// Time of a link found in the chunk index. For [`SortKey::EntryTime`] this is the start of the chunk
+    pub fn link_time(&self, link: &Link, index: &Index) -> Option<DateTime<Utc>> {
+        match self {
+            SortKey::EntryTime => Some(get_datetime_for_duration(index.from)),
+            SortKey::LinkCreated => get_datetime_for_timestamp(link.timestamp),
+            SortKey::Custom(key) => key(&link.tag),
+        }
+    }
+
+    /// Time of a loaded item
+    pub fn item_time<T: IndexableEntry>(&self, item: &IndexedItem<T>) -> Option<DateTime<Utc>> {
+        match self {
+            SortKey::EntryTime => Some(item.entry.entry_time()),
+            _ => self.link_time(&item.link, &item.index),
+        }
+    }
+}
+
+/// Total order used for links returned by search functions.
+/// Links are ordered by their creation timestamp, then by the raw bytes of their target hash and finally by the raw bytes
+/// of the hash of their create link action. Descending searches return the exact reverse of this order
+pub fn compare_links(a: &Link, b: &Link) -> Ordering {
+    a.timestamp
+        .cmp(&b.timestamp)
+        .then_with(|| a.target.cmp(&b.target))
+        .then_with(|| a.create_link_hash.cmp(&b.create_link_hash))
+}
+
+/// Total order used for loaded items returned by search functions.
+/// Items are ordered by [`IndexableEntry::entry_time()`], then by the raw bytes of the hash of the action which created the entry
+/// and finally by the order of the index links which pointed to them as defined by [`compare_links()`]. Descending searches return
+/// the exact reverse of this order
+pub fn compare_indexed_items<T: IndexableEntry>(a: &IndexedItem<T>, b: &IndexedItem<T>) -> Ordering {
+    a.entry
+        .entry_time()
+        .cmp(&b.entry.entry_time())
+        .then_with(|| a.action.as_hash().cmp(b.action.as_hash()))
+        .then_with(|| compare_links(&a.link, &b.link))
+}
+
+/// Total order used for links returned by search functions using sort_key.
+/// Links are ordered by the time given by [`SortKey::link_time()`] and then as defined by [`compare_links()`]
+pub fn compare_links_by(sort_key: &SortKey, a: (&Link, &Index), b: (&Link, &Index)) -> Ordering {
+    sort_key
+        .link_time(a.0, a.1)
+        .cmp(&sort_key.link_time(b.0, b.1))
+        .then_with(|| compare_links(a.0, b.0))
+}
+
+/// Total order used for loaded items returned by search functions using sort_key.
+/// Items are ordered by the time given by [`SortKey::item_time()`] and then as defined by [`compare_indexed_items()`]
+pub fn compare_indexed_items_by<T: IndexableEntry>(
+    sort_key: &SortKey,
+    a: &IndexedItem<T>,
+    b: &IndexedItem<T>,
+) -> Ordering {
+    sort_key
+        .item_time(a)
+        .cmp(&sort_key.item_time(b))
+        .then_with(|| compare_indexed_items(a, b))
+}
+
+/// Total order used for chunks returned by search functions. Chunks are ordered by the start and then end of their time period
+pub fn compare_chunks(a: &EntryChunkIndex, b: &EntryChunkIndex) -> Ordering {
+    a.index.cmp(&b.index)
+}
+
+/// Sort links in place using [`compare_links()`] in the direction given by order
+pub(crate) fn sort_links(links: &mut [Link], order: &Order) {
+    match order {
+        Order::Desc => links.sort_by(|a, b| compare_links(b, a)),
+        Order::Asc => links.sort_by(compare_links),
+    }
+}
+
+/// Sort links and the chunks they were found in using [`compare_links_by()`] in the direction given by order
+pub(crate) fn sort_links_by(links: &mut [(Link, Index)], sort_key: &SortKey, order: &Order) {
+    match order {
+        Order::Desc => links.sort_by(|a, b| compare_links_by(sort_key, (&b.0, &b.1), (&a.0, &a.1))),
+        Order::Asc => links.sort_by(|a, b| compare_links_by(sort_key, (&a.0, &a.1), (&b.0, &b.1))),
+    }
+}
+
+/// Sort loaded items in place using [`compare_indexed_items_by()`] in the direction given by order
+pub(crate) fn sort_indexed_items<T: IndexableEntry>(
+    items: &mut [IndexedItem<T>],
+    sort_key: &SortKey,
+    order: &Order,
+) {
+    match order {
+        Order::Desc => items.sort_by(|a, b| compare_indexed_items_by(sort_key, b, a)),
+        Order::Asc => items.sort_by(|a, b| compare_indexed_items_by(sort_key, a, b)),
+    }
+}
+
+mod ordering_tests {
+    #[test]
+    fn test_compare_links_tiebreak() {
+        use crate::ordering::{compare_links, sort_links};
+        use crate::Order;
+        use hdk::prelude::*;
+        use std::cmp::Ordering;
+
+        let make_link = |micros: i64, target: u8, create: u8| Link {
+            target: EntryHash::from_raw_36(vec![target; 36]).into(),
+            timestamp: Timestamp::from_micros(micros),
+            zome_index: ZomeIndex::new(0),
+            link_type: LinkType::new(0),
+            tag: LinkTag::new("test"),
+            create_link_hash: ActionHash::from_raw_36(vec![create; 36]),
+        };
+        let early = make_link(1, 9, 9);
+        let late_a = make_link(2, 1, 5);
+        let late_b = make_link(2, 2, 1);
+        let late_b_dup = make_link(2, 2, 3);
+
+        assert_eq!(compare_links(&early, &late_a), Ordering::Less);
+        assert_eq!(compare_links(&late_a, &late_b), Ordering::Less);
+        assert_eq!(compare_links(&late_b, &late_b_dup), Ordering::Less);
+
+        let mut links = vec![late_b_dup.clone(), late_a.clone(), early.clone(), late_b.clone()];
+        sort_links(&mut links, &Order::Asc);
+        assert_eq!(links, vec![early.clone(), late_a.clone(), late_b.clone(), late_b_dup.clone()]);
+        sort_links(&mut links, &Order::Desc);
+        assert_eq!(links, vec![late_b_dup, late_b, late_a, early]);
+    }
+}
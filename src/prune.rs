@@ -0,0 +1,330 @@
+use chrono::{DateTime, Utc};
+use hdk::prelude::*;
+
+use crate::bfs::find_chunks_for_time_span;
+use crate::entries::{Index, IndexRoot};
+use crate::errors::{IndexError, IndexResult};
+use crate::fetch::{get_filtered_link_details_batch, LinkWithDeletes};
+use crate::methods::log_change;
+use crate::query::QueryContext;
+use crate::utils::get_datetime_for_duration;
+use crate::{Bound, ChangeKind, ChunkRemovals, LinkTagFilter, Order, PruneReport, TimeRange, INGEST_LOG};
+
+/// Remove up to max_chunks of the oldest chunks in index which end at or before cutoff. The tree is walked from resume_from, or
+/// from the oldest chunk in the index when it is None. The walk, and the reads and deletes removing each chunk, are made within the
+/// budget of ctx. Chunks are removed one at a time, oldest first, until max_chunks have been removed or the budget does not allow
+/// the next one to be. Fails with IndexError::BudgetExhausted if not even one chunk could be removed.
+/// Only links created by the calling agent are deleted. Index links in each chunk are deleted along with the time_path links
+/// pointing back to the chunk from the indexed entries. Once no index links are left in a chunk, path links are deleted from the
+/// chunk up towards the root of the index for as long as the path they point to has nothing left below it. When INGEST_LOG is set
+/// each entry left without any index links is recorded as removed in the ingest log
+pub(crate) fn prune_before<ILT: LinkTypeFilterExt + Clone, PLT: Clone>(
+    index: String,
+    cutoff: DateTime<Utc>,
+    max_chunks: usize,
+    resume_from: Option<DateTime<Utc>>,
+    ctx: &mut QueryContext,
+    index_link_type: ILT,
+    path_link_type: PLT,
+) -> IndexResult<PruneReport>
+where
+    ScopedLinkType: TryFrom<ILT, Error = WasmError> + TryFrom<PLT, Error = WasmError>,
+{
+    if max_chunks == 0 {
+        return Err(IndexError::RequestError("max_chunks must be greater than zero"));
+    };
+    let mut report = PruneReport::default();
+    let root = IndexRoot::Index(index.clone());
+    let range = TimeRange {
+        earliest: resume_from.map(Bound::Included).unwrap_or(Bound::Unbounded),
+        latest: Bound::Excluded(cutoff),
+        order: Order::Asc,
+    };
+    let range = match range.resolve(&root, path_link_type.clone(), ctx)? {
+        Some(range) => range,
+        None => {
+            report.complete = true;
+            return Ok(report);
+        }
+    };
+    let chunks = find_chunks_for_time_span(
+        range.earliest,
+        range.latest,
+        &root,
+        &Order::Asc,
+        path_link_type.clone(),
+        ctx,
+    )?
+    .into_iter()
+    .filter(|(_chunk_path, chunk)| get_datetime_for_duration(chunk.until) <= cutoff)
+    .collect::<Vec<(Path, Index)>>();
+    report.complete = true;
+    let author = agent_info()?.agent_latest_pubkey;
+    let mut removed_targets = vec![];
+    for (chunk_number, (chunk_path, chunk)) in chunks.into_iter().enumerate() {
+        if chunk_number == max_chunks {
+            report.complete = false;
+            break;
+        };
+        match remove_chunk(&chunk_path, &author, &mut report, ctx, index_link_type.clone(), path_link_type.clone()) {
+            Ok(mut targets) => {
+                removed_targets.append(&mut targets);
+                report.chunks.push(chunk);
+            }
+            Err(IndexError::BudgetExhausted) if !report.chunks.is_empty() => {
+                report.complete = false;
+                break;
+            }
+            Err(error) => return Err(error),
+        };
+    }
+    if !report.complete {
+        report.continuation = report
+            .chunks
+            .last()
+            .map(|chunk| get_datetime_for_duration(chunk.until));
+    };
+    if *INGEST_LOG {
+        for target in removed_targets {
+            log_change(
+                index.clone(),
+                target,
+                ChangeKind::Removed,
+                index_link_type.clone(),
+                path_link_type.clone(),
+            )?;
+        }
+    };
+    Ok(report)
+}
+
+/// Delete the calling agent's links from chunk_path to its entries and the time_path links from those entries back to the chunk.
+/// If no index links are left in the chunk the path links leading to it are then deleted, see [`unlink_emptied_path()`].
+/// The links are read before anything is deleted, and nothing is deleted unless the budget allows every delete along with the
+/// walk up the tree. Returns the entries which were left without any index links
+fn remove_chunk<ILT: LinkTypeFilterExt + Clone, PLT: Clone>(
+    chunk_path: &Path,
+    author: &AgentPubKey,
+    report: &mut PruneReport,
+    ctx: &mut QueryContext,
+    index_link_type: ILT,
+    path_link_type: PLT,
+) -> IndexResult<Vec<AnyLinkableHash>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let is_own = |(create, _deletes): &LinkWithDeletes| create.action().author() == author;
+    let chunk_hash: AnyLinkableHash = chunk_path.path_entry_hash()?.into();
+    let (index_links, other_index_links): (Vec<LinkWithDeletes>, Vec<LinkWithDeletes>) = live_link_details(
+        vec![chunk_hash.clone()],
+        index_link_type.clone(),
+        &LinkTagFilter::All,
+        ctx,
+    )?
+    .into_iter()
+    .flatten()
+    .partition(is_own);
+    let mut targets = index_links.iter().filter_map(link_target).collect::<Vec<AnyLinkableHash>>();
+    targets.sort();
+    targets.dedup();
+    let time_path_links = live_link_details(
+        targets.clone(),
+        index_link_type,
+        &LinkTagFilter::Exact(LinkTag::new("time_path")),
+        ctx,
+    )?;
+
+    //Entries which are still indexed by a link this does not delete are kept out of the ingest log
+    let mut removed_targets = vec![];
+    let mut time_path_deletes = vec![];
+    for (target, links) in targets.into_iter().zip(time_path_links) {
+        let (deleted, kept): (Vec<LinkWithDeletes>, Vec<LinkWithDeletes>) = links
+            .into_iter()
+            .partition(|link| is_own(link) && link_target(link).as_ref() == Some(&chunk_hash));
+        let indexed_by_others = other_index_links
+            .iter()
+            .any(|link| link_target(link).as_ref() == Some(&target));
+        if kept.is_empty() && !indexed_by_others {
+            removed_targets.push(target);
+        };
+        time_path_deletes.extend(deleted);
+    }
+
+    //Each level above the chunk takes one get_link_details call and, when the caller linked it, one delete
+    let levels = chunk_path.as_ref().len() - 1;
+    if !ctx.can_afford(index_links.len() + time_path_deletes.len() + 2 * levels, 0) {
+        return Err(IndexError::BudgetExhausted);
+    };
+    for (create, _deletes) in index_links {
+        ctx.record_delete_link()?;
+        delete_link(create.action_address().clone())?;
+        report.index_links += 1;
+    }
+    for (create, _deletes) in time_path_deletes {
+        ctx.record_delete_link()?;
+        delete_link(create.action_address().clone())?;
+        report.time_path_links += 1;
+    }
+    if other_index_links.is_empty() {
+        unlink_emptied_path(chunk_path, author, report, ctx, path_link_type)?;
+    };
+    Ok(removed_targets)
+}
+
+/// Walk up the tree from an emptied path deleting the calling agent's links to it. A parent is emptied once no links are left
+/// from it to other paths, and the walk stops at the first parent which is not or which is still linked to by another agent.
+/// The index root is kept so that the index can be written to again
+fn unlink_emptied_path<PLT>(
+    path: &Path,
+    author: &AgentPubKey,
+    report: &mut PruneReport,
+    ctx: &mut QueryContext,
+    path_link_type: PLT,
+) -> IndexResult<()>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let link_type = ScopedLinkType::try_from(path_link_type)?;
+    let link_type = LinkTypeFilter::single_type(link_type.zome_index, link_type.zome_type);
+    let mut emptied = path.clone();
+    while emptied.as_ref().len() > 1 {
+        let components = emptied.as_ref();
+        let parent = Path::from(components[..components.len() - 1].to_vec());
+        let emptied_hash: AnyLinkableHash = emptied.path_entry_hash()?.into();
+        let links = live_link_details(vec![parent.path_entry_hash()?.into()], link_type.clone(), &LinkTagFilter::All, ctx)?
+            .pop()
+            .unwrap_or_default();
+        let (removed, remaining): (Vec<LinkWithDeletes>, Vec<LinkWithDeletes>) = links.into_iter().partition(|link| {
+            link.0.action().author() == author && link_target(link).as_ref() == Some(&emptied_hash)
+        });
+        for (create, _deletes) in removed {
+            ctx.record_delete_link()?;
+            delete_link(create.action_address().clone())?;
+        }
+        if !remaining.is_empty() || parent.as_ref().len() == 1 {
+            break;
+        };
+        report.paths.push(parent.clone());
+        emptied = parent;
+    }
+    Ok(())
+}
+
+/// Get the link details from each of bases, as get_filtered_link_details_batch does, without the links which have been deleted
+fn live_link_details(
+    bases: Vec<AnyLinkableHash>,
+    link_type: impl LinkTypeFilterExt,
+    link_tag: &LinkTagFilter,
+    ctx: &mut QueryContext,
+) -> IndexResult<Vec<Vec<LinkWithDeletes>>> {
+    Ok(get_filtered_link_details_batch(bases, link_type, link_tag, ctx)?
+        .into_iter()
+        .map(|details| details.into_iter().filter(|(_create, deletes)| deletes.is_empty()).collect())
+        .collect())
+}
+
+/// Target of the link created by a create link action
+fn link_target((create, _deletes): &LinkWithDeletes) -> Option<AnyLinkableHash> {
+    match create.action() {
+        Action::CreateLink(create_link) => Some(create_link.target_address.clone()),
+        _ => None,
+    }
+}
+
+/// Delete the index links in range created by the calling agent along with the time_path links they created from the indexed
+/// entries back to each chunk. Links which have already been deleted are skipped. Returns the number of links removed from each
+/// chunk that had any
+pub(crate) fn remove_author_links<ILT: LinkTypeFilterExt + Clone, PLT: Clone>(
+    index: String,
+    range: TimeRange,
+    ctx: &mut QueryContext,
+    index_link_type: ILT,
+    path_link_type: PLT,
+) -> IndexResult<Vec<ChunkRemovals>>
+where
+    ScopedLinkType: TryFrom<ILT, Error = WasmError> + TryFrom<PLT, Error = WasmError>,
+{
+    let root = IndexRoot::Index(index.clone());
+    let range = match range.resolve(&root, path_link_type.clone(), ctx)? {
+        Some(range) => range,
+        None => return Ok(vec![]),
+    };
+    let chunks = find_chunks_for_time_span(
+        range.earliest,
+        range.latest,
+        &root,
+        &Order::Asc,
+        path_link_type.clone(),
+        ctx,
+    )?;
+    let author = agent_info()?.agent_latest_pubkey;
+    let is_own_live_link = |(create, deletes): &LinkWithDeletes| {
+        *create.action().author() == author && deletes.is_empty()
+    };
+
+    let chunk_hashes = chunks
+        .iter()
+        .map(|(chunk_path, _chunk)| Ok(chunk_path.path_entry_hash()?.into()))
+        .collect::<IndexResult<Vec<AnyLinkableHash>>>()?;
+    let index_links = get_filtered_link_details_batch(chunk_hashes.clone(), index_link_type.clone(), &LinkTagFilter::All, ctx)?
+        .into_iter()
+        .map(|details| details.into_iter().filter(is_own_live_link).collect())
+        .collect::<Vec<Vec<LinkWithDeletes>>>();
+    //An entry indexed more than once would otherwise have its time_path links fetched, and deleted, once per index link
+    let mut targets = index_links
+        .iter()
+        .flatten()
+        .filter_map(|(create, _deletes)| match create.action() {
+            Action::CreateLink(create_link) => Some(create_link.target_address.clone()),
+            _ => None,
+        })
+        .collect::<Vec<AnyLinkableHash>>();
+    targets.sort();
+    targets.dedup();
+    let time_path_links = get_filtered_link_details_batch(
+        targets.clone(),
+        index_link_type.clone(),
+        &LinkTagFilter::Exact(LinkTag::new("time_path")),
+        ctx,
+    )?
+    .into_iter()
+    .flatten()
+    .filter(is_own_live_link)
+    .collect::<Vec<LinkWithDeletes>>();
+
+    let mut removals = vec![];
+    for (((_chunk_path, chunk), chunk_hash), index_links) in chunks.into_iter().zip(chunk_hashes).zip(index_links) {
+        let mut removed = ChunkRemovals {
+            index: chunk,
+            index_links: 0,
+            time_path_links: 0,
+        };
+        for (create, _deletes) in index_links {
+            delete_link(create.action_address().clone())?;
+            removed.index_links += 1;
+        }
+        for (create, _deletes) in time_path_links.iter() {
+            if let Action::CreateLink(create_link) = create.action() {
+                if create_link.target_address == chunk_hash {
+                    delete_link(create.action_address().clone())?;
+                    removed.time_path_links += 1;
+                };
+            };
+        }
+        if removed.index_links > 0 || removed.time_path_links > 0 {
+            removals.push(removed);
+        };
+    }
+    if *INGEST_LOG {
+        for target in targets {
+            log_change(
+                index.clone(),
+                target,
+                ChangeKind::Removed,
+                index_link_type.clone(),
+                path_link_type.clone(),
+            )?;
+        }
+    };
+    Ok(removals)
+}
@@ -0,0 +1,185 @@
+use chrono::{DateTime, Utc};
+use hdk::prelude::*;
+
+use crate::dfs::SearchState;
+use crate::entries::{Index, IndexType};
+use crate::explain::QueryPlan;
+use crate::errors::{IndexError, IndexResult};
+use crate::utils::get_datetime_for_duration;
+use crate::{Order, QueryOptions};
+
+/// Options of a running search along with the host calls and chunks it has used so far. When the search is being explained
+/// the context also builds up a QueryPlan describing the search
+pub(crate) struct QueryContext {
+    pub(crate) options: QueryOptions,
+    pub(crate) host_calls: usize,
+    pub(crate) chunks_read: usize,
+    pub(crate) plan: Option<QueryPlan>,
+}
+
+impl Default for QueryContext {
+    fn default() -> Self {
+        QueryContext::new(QueryOptions::default())
+    }
+}
+
+impl QueryContext {
+    pub(crate) fn new(options: QueryOptions) -> QueryContext {
+        QueryContext {
+            options,
+            host_calls: 0,
+            chunks_read: 0,
+            plan: None,
+        }
+    }
+
+    /// Create a context which records a QueryPlan for the search
+    pub(crate) fn explain(options: QueryOptions) -> QueryContext {
+        QueryContext {
+            plan: Some(QueryPlan::default()),
+            ..QueryContext::new(options)
+        }
+    }
+
+    pub(crate) fn has_budget(&self) -> bool {
+        self.options.budget.is_some()
+    }
+
+    /// Check if the budget allows a further host_calls host calls to be made to read a further chunks chunks
+    pub(crate) fn can_afford(&self, host_calls: usize, chunks: usize) -> bool {
+        match &self.options.budget {
+            Some(budget) => {
+                budget
+                    .max_host_calls
+                    .map(|max| self.host_calls + host_calls <= max)
+                    .unwrap_or(true)
+                    && budget
+                        .max_chunks
+                        .map(|max| self.chunks_read + chunks <= max)
+                        .unwrap_or(true)
+            }
+            None => true,
+        }
+    }
+
+    /// Record a host call. Fails with IndexError::BudgetExhausted if the budget does not allow it
+    fn record_host_call(&mut self) -> IndexResult<()> {
+        if !self.can_afford(1, 0) {
+            return Err(IndexError::BudgetExhausted);
+        };
+        self.host_calls += 1;
+        Ok(())
+    }
+
+    /// Record a batched get_links host call. Fails with IndexError::BudgetExhausted if the budget does not allow it
+    pub(crate) fn record_get_links(&mut self) -> IndexResult<()> {
+        self.record_host_call()?;
+        if let Some(plan) = self.plan.as_mut() {
+            plan.get_links_calls += 1;
+        };
+        Ok(())
+    }
+
+    /// Record a batched get host call. Fails with IndexError::BudgetExhausted if the budget does not allow it
+    pub(crate) fn record_get(&mut self) -> IndexResult<()> {
+        self.record_host_call()?;
+        if let Some(plan) = self.plan.as_mut() {
+            plan.get_calls += 1;
+        };
+        Ok(())
+    }
+
+    /// Record a delete_link host call. Fails with IndexError::BudgetExhausted if the budget does not allow it
+    pub(crate) fn record_delete_link(&mut self) -> IndexResult<()> {
+        self.record_host_call()
+    }
+
+    /// Record chunks being read. Fails with IndexError::BudgetExhausted if the budget does not allow it
+    pub(crate) fn record_chunks(&mut self, chunks: &[(Path, Index)]) -> IndexResult<()> {
+        if !self.can_afford(0, chunks.len()) {
+            return Err(IndexError::BudgetExhausted);
+        };
+        self.chunks_read += chunks.len();
+        if let Some(plan) = self.plan.as_mut() {
+            plan.chunks_read
+                .extend(chunks.iter().map(|(_chunk_path, index)| index.clone()));
+        };
+        Ok(())
+    }
+
+    /// Record the path a search starts from and the levels it will search below it
+    pub(crate) fn record_root(&mut self, root: &Path, levels: &[IndexType]) {
+        if let Some(plan) = self.plan.as_mut() {
+            plan.root = Some(root.clone());
+            plan.root_levels = levels.to_vec();
+        };
+    }
+
+    /// Record the paths found at a level of the tree
+    pub(crate) fn record_level(&mut self, level: &IndexType, visited: &[Path], pruned: &[Path]) {
+        if let Some(plan) = self.plan.as_mut() {
+            plan.record_level(level, visited, pruned);
+        };
+    }
+
+    /// Record the graph built by a DFS search
+    pub(crate) fn record_search_graph(&mut self, search_state: &SearchState) {
+        if let Some(plan) = self.plan.as_mut() {
+            plan.search_graph = Some(search_state.to_dot());
+        };
+    }
+}
+
+/// Time a search which ran out of budget should continue from given the last chunk it read. Fails with
+/// IndexError::BudgetExhausted if no chunk was read, as repeating the search from the start of its range would run out of budget
+/// at the same point
+pub(crate) fn get_continuation(last_chunk: Option<&Index>, order: &Order) -> IndexResult<DateTime<Utc>> {
+    match (last_chunk, order) {
+        (Some(last_chunk), Order::Desc) => {
+            Ok(get_datetime_for_duration(last_chunk.from) - chrono::Duration::nanoseconds(1))
+        }
+        (Some(last_chunk), Order::Asc) => Ok(get_datetime_for_duration(last_chunk.until)),
+        (None, _) => Err(IndexError::BudgetExhausted),
+    }
+}
+
+mod query_tests {
+    #[test]
+    fn test_budget() {
+        use crate::entries::Index;
+        use crate::errors::IndexError;
+        use crate::query::{get_continuation, QueryContext};
+        use crate::utils::get_datetime_for_duration;
+        use crate::{Order, QueryBudget, QueryOptions};
+        use hdk::prelude::*;
+        use std::time::Duration;
+
+        let mut ctx = QueryContext::new(QueryOptions::default().with_budget(QueryBudget {
+            max_host_calls: Some(3),
+            max_chunks: Some(1),
+        }));
+        assert!(ctx.can_afford(2, 1));
+        assert!(!ctx.can_afford(2, 2));
+        let chunk = Index {
+            from: Duration::from_secs(10),
+            until: Duration::from_secs(20),
+        };
+        let chunks = vec![(Path::from("chunk"), chunk.clone())];
+        ctx.record_get_links().unwrap();
+        ctx.record_chunks(&chunks).unwrap();
+        assert!(!ctx.can_afford(1, 1));
+        assert!(ctx.record_chunks(&chunks).is_err());
+        ctx.record_get().unwrap();
+        ctx.record_get_links().unwrap();
+        assert!(ctx.record_get().is_err());
+        assert!(QueryContext::default().can_afford(usize::MAX, usize::MAX));
+
+        assert_eq!(
+            get_continuation(Some(&chunk), &Order::Asc).unwrap(),
+            get_datetime_for_duration(chunk.until)
+        );
+        assert!(get_continuation(Some(&chunk), &Order::Desc).unwrap() < get_datetime_for_duration(chunk.from));
+        //A search which ran out of budget before reading a chunk would stop at the same point if it was repeated
+        assert!(matches!(get_continuation(None, &Order::Asc), Err(IndexError::BudgetExhausted)));
+    }
+}
@@ -0,0 +1,178 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hdk::prelude::*;
+
+use crate::entries::IndexRoot;
+use crate::errors::IndexResult;
+use crate::query::QueryContext;
+use crate::search::find_edge_chunk;
+use crate::utils::{get_datetime_for_duration, get_latest_allowed_time};
+use crate::{Bound, Order, TimeRange, MAX_FUTURE_SKEW};
+
+/// Time range with both ends resolved to inclusive times. from and until follow the convention used throughout the searches:
+/// from is the end results start at so from is greater than until for Order::Desc
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct NormalizedRange {
+    pub(crate) earliest: DateTime<Utc>,
+    pub(crate) latest: DateTime<Utc>,
+    pub(crate) order: Order,
+}
+
+impl NormalizedRange {
+    pub(crate) fn from(&self) -> DateTime<Utc> {
+        match self.order {
+            Order::Desc => self.latest,
+            Order::Asc => self.earliest,
+        }
+    }
+
+    pub(crate) fn until(&self) -> DateTime<Utc> {
+        match self.order {
+            Order::Desc => self.earliest,
+            Order::Asc => self.latest,
+        }
+    }
+}
+
+impl TimeRange {
+    /// Range covering from & until inclusive. Results are returned newest first when from is later than until
+    pub fn new(from: DateTime<Utc>, until: DateTime<Utc>) -> TimeRange {
+        let order = if from > until { Order::Desc } else { Order::Asc };
+        let (earliest, latest) = match order {
+            Order::Desc => (until, from),
+            Order::Asc => (from, until),
+        };
+        TimeRange {
+            earliest: Bound::Included(earliest),
+            latest: Bound::Included(latest),
+            order,
+        }
+    }
+
+    /// Range between earliest & latest with results returned in order
+    pub fn between(earliest: Bound, latest: Bound, order: Order) -> TimeRange {
+        TimeRange {
+            earliest,
+            latest,
+            order,
+        }
+    }
+
+    /// Range covering the rest of this range after a page of results which ended with continuation
+    pub fn resume_from(&self, continuation: DateTime<Utc>) -> TimeRange {
+        let mut range = self.clone();
+        match range.order {
+            Order::Desc => range.latest = Bound::Included(continuation),
+            Order::Asc => range.earliest = Bound::Included(continuation),
+        };
+        range
+    }
+
+    /// Resolve both ends of the range into inclusive times. Excluded bounds are moved one nanosecond inside of the range and
+    /// unbounded ends are resolved to the UNIX epoch or the latest representable time. Returns None if the range contains no time
+    pub(crate) fn normalize(&self) -> Option<NormalizedRange> {
+        self.normalize_with(Utc.timestamp_opt(0, 0).unwrap(), DateTime::<Utc>::MAX_UTC)
+    }
+
+    /// Resolve both ends of the range into inclusive times for a search of index. Unbounded ends are resolved to the start of the
+    /// oldest or end of the newest chunk in the index, found by following the oldest or newest paths down the tree. Chunks starting
+    /// more than [`struct@crate::MAX_FUTURE_SKEW`] after sys_time are ignored.
+    /// Returns None if the range contains no time or, for an unbounded range, has nothing indexed inside of it
+    pub(crate) fn resolve<PLT: Clone>(
+        &self,
+        index: &IndexRoot,
+        path_link_type: PLT,
+        ctx: &mut QueryContext,
+    ) -> IndexResult<Option<NormalizedRange>>
+    where
+        ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+    {
+        if self.earliest != Bound::Unbounded && self.latest != Bound::Unbounded {
+            return Ok(self.normalize());
+        };
+        //Chunks starting further in the future than MAX_FUTURE_SKEW can only come from agents with skewed clocks
+        let not_after = Some(get_latest_allowed_time(*MAX_FUTURE_SKEW)?);
+        let earliest = match self.earliest {
+            Bound::Unbounded => {
+                match find_edge_chunk(index, &Order::Asc, None::<LinkTypeFilter>, not_after, path_link_type.clone(), ctx)? {
+                    Some((_chunk_path, chunk)) => get_datetime_for_duration(chunk.from),
+                    None => return Ok(None),
+                }
+            }
+            _ => Utc.timestamp_opt(0, 0).unwrap(),
+        };
+        let latest = match self.latest {
+            Bound::Unbounded => {
+                match find_edge_chunk(index, &Order::Desc, None::<LinkTypeFilter>, not_after, path_link_type, ctx)? {
+                    Some((_chunk_path, chunk)) => get_datetime_for_duration(chunk.until) - Duration::nanoseconds(1),
+                    None => return Ok(None),
+                }
+            }
+            _ => DateTime::<Utc>::MAX_UTC,
+        };
+        Ok(self.normalize_with(earliest, latest))
+    }
+
+    fn normalize_with(
+        &self,
+        unbounded_earliest: DateTime<Utc>,
+        unbounded_latest: DateTime<Utc>,
+    ) -> Option<NormalizedRange> {
+        let earliest = match self.earliest {
+            Bound::Included(time) => time,
+            Bound::Excluded(time) => time + Duration::nanoseconds(1),
+            Bound::Unbounded => unbounded_earliest,
+        };
+        let latest = match self.latest {
+            Bound::Included(time) => time,
+            Bound::Excluded(time) => time - Duration::nanoseconds(1),
+            Bound::Unbounded => unbounded_latest,
+        };
+        if earliest > latest {
+            return None;
+        };
+        Some(NormalizedRange {
+            earliest,
+            latest,
+            order: self.order.clone(),
+        })
+    }
+}
+
+mod range_tests {
+    #[test]
+    fn test_normalize() {
+        use crate::{Bound, Order, TimeRange};
+        use chrono::{Duration, TimeZone, Utc};
+
+        let early = Utc.timestamp_opt(1_000, 0).unwrap();
+        let late = early + Duration::days(1);
+
+        //Reversed endpoints search newest first over the same span
+        let desc = TimeRange::new(late, early).normalize().unwrap();
+        assert_eq!((desc.earliest, desc.latest, desc.order.clone()), (early, late, Order::Desc));
+        assert_eq!((desc.from(), desc.until()), (late, early));
+        let asc = TimeRange::new(early, late).normalize().unwrap();
+        assert_eq!((asc.from(), asc.until(), asc.order), (early, late, Order::Asc));
+
+        //Equal endpoints cover a single instant
+        let instant = TimeRange::new(early, early).normalize().unwrap();
+        assert_eq!((instant.earliest, instant.latest), (early, early));
+
+        let excluded = TimeRange::between(Bound::Excluded(early), Bound::Excluded(late), Order::Asc)
+            .normalize()
+            .unwrap();
+        assert!(excluded.earliest > early && excluded.latest < late);
+        assert!(TimeRange::between(Bound::Excluded(early), Bound::Included(early), Order::Asc)
+            .normalize()
+            .is_none());
+
+        let open = TimeRange::between(Bound::Unbounded, Bound::Unbounded, Order::Desc)
+            .normalize()
+            .unwrap();
+        assert_eq!(open.until(), Utc.timestamp_opt(0, 0).unwrap());
+        assert!(open.from() > late);
+
+        let resumed = TimeRange::new(late, early).resume_from(late - Duration::hours(1));
+        assert_eq!(resumed.normalize().unwrap().from(), late - Duration::hours(1));
+    }
+}
@@ -1,9 +1,12 @@
 use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Timelike, Utc};
-use hdk::{hash_path::path::Component, prelude::*};
+use hdk::prelude::*;
 
-use crate::entries::IndexType;
-use crate::errors::{IndexError, IndexResult};
-use crate::INDEX_DEPTH;
+use crate::entries::{Index, IndexRoot, IndexType, TimeIndex};
+use crate::errors::IndexResult;
+use crate::fetch::{get_children_paths_batch, get_filtered_links_batch};
+use crate::query::QueryContext;
+use crate::utils::{get_datetime_for_duration, get_time_path_start};
+use crate::{LinkTagFilter, Order, SearchStrategy, SortKey, DEFAULT_INDEX_DEPTH, INDEX_DEPTH};
 
 pub(crate) fn get_naivedatetime(
     from: &DateTime<Utc>,
@@ -80,64 +83,211 @@ pub(crate) fn get_naivedatetime(
     }
 }
 
-/// Tries to find the newest time period one level down from current path position
-/// Returns path passed in params if maximum depth has been reached
-pub(crate) fn find_newest_time_path<
-    T: TryFrom<SerializedBytes, Error = SerializedBytesError> + Into<u32>,
-    PLT: Into<ScopedLinkType>
->(
-    path: Path,
-    time_index: IndexType,
-    path_link_type: PLT
-) -> IndexResult<Path> {
-    match time_index {
-        IndexType::Year => (),
-        IndexType::Month => (),
-        IndexType::Day => (),
-        IndexType::Hour => {
-            if INDEX_DEPTH.contains(&time_index) {
-                ()
-            } else {
-                return Ok(path);
-            }
-        }
-        IndexType::Minute => {
-            if INDEX_DEPTH.contains(&time_index) {
-                ()
-            } else {
-                return Ok(path);
-            }
-        }
-        IndexType::Second => {
-            if INDEX_DEPTH.contains(&time_index) {
-                ()
-            } else {
-                return Ok(path);
-            }
-        }
+/// Order time paths so the one with the oldest (Order::Asc) or newest (Order::Desc) time period in its last component comes first
+fn sort_edge_time_paths(paths: Vec<Path>, edge: &Order) -> IndexResult<Vec<Path>> {
+    let mut paths = paths
+        .into_iter()
+        .map(|path| {
+            let time = TimeIndex::try_from(path.as_ref().last().unwrap().clone())?.0;
+            Ok((time, path))
+        })
+        .collect::<IndexResult<Vec<(u32, Path)>>>()?;
+    match edge {
+        Order::Desc => paths.sort_by_key(|(time, _path)| std::cmp::Reverse(*time)),
+        Order::Asc => paths.sort_by_key(|(time, _path)| *time),
     };
-    //debug!("Finding links on IndexType: {:#?}\n\n", time_index);
+    Ok(paths.into_iter().map(|(_time, path)| path).collect())
+}
+
+/// Frontier size above which an adaptive DFS search always switches to fetching the rest of the tree level by level.
+/// Visiting the frontier one branch at a time takes at least a host call per branch, while fetching it level by level takes one
+/// batched call per level of the tree, of which there are at most six. Past this many branches the batched calls are cheaper even
+/// if only a few of the branches would have been visited, and as chunks are still read one at a time up to the limit the switch
+/// does not read more chunks than the dfs search would have
+pub(crate) const MAX_DFS_FRONTIER: usize = 32;
+
+/// Pick the strategy used for a SearchStrategy::Auto search.
+/// Searches without a limit, or which are not ordered by entry time, must visit every chunk in the time span and so use BFS. Otherwise
+/// DFS is used unless the span cannot hold more chunks than the limit, in which case DFS would end up visiting every chunk anyway
+pub(crate) fn choose_search_strategy(
+    from: &DateTime<Utc>,
+    until: &DateTime<Utc>,
+    limit: Option<usize>,
+    sort_key: &SortKey,
+    chunk_interval: std::time::Duration,
+) -> SearchStrategy {
+    let limit = match (limit, sort_key) {
+        (Some(limit), SortKey::EntryTime) => limit,
+        _ => return SearchStrategy::Bfs,
+    };
+    let span = (*from - *until).num_milliseconds().unsigned_abs() as u128;
+    let max_chunks = span / chunk_interval.as_millis().max(1) + 1;
+    if max_chunks <= limit as u128 {
+        SearchStrategy::Bfs
+    } else {
+        SearchStrategy::Dfs
+    }
+}
 
-    //Pretty sure this filter and sort logic can be faster; first rough pass to get basic pieces in place
-    let mut links = path.typed(path_link_type)?.children_paths()?;
-    if links.len() == 0 {
-        return Err(IndexError::Wasm(wasm_error!(WasmErrorInner::Host(String::from("Could not find any time paths for path")))));
+/// Check if an adaptive DFS search should stop descending one branch at a time and instead fetch all of its remaining frontier
+/// level by level. This happens when the frontier is larger than MAX_DFS_FRONTIER or when the density of results observed in the
+/// leaves searched so far suggests the rest of the frontier will have to be visited to find the remaining results. While nothing
+/// has been found the density is taken to be one result in the next leaf, so each empty leaf searched makes a switch more likely
+pub(crate) fn should_expand_level_wise(
+    frontier: usize,
+    remaining: usize,
+    found: usize,
+    leaves_searched: usize,
+) -> bool {
+    if frontier <= 1 || leaves_searched == 0 {
+        return false;
+    };
+    if frontier > MAX_DFS_FRONTIER {
+        return true;
+    };
+    let leaves_needed = match found {
+        0 => remaining.saturating_mul(leaves_searched + 1),
+        found => remaining.saturating_mul(leaves_searched).div_ceil(found),
     };
-    links.sort_by(|a, b| {
-        let a_val: Vec<Component> = a.path.to_owned().into();
-        let b_val: Vec<Component> = b.path.to_owned().into();
-        let a_u32: u32 = T::try_from(SerializedBytes::from(UnsafeBytes::from(
-            a_val[1].as_ref().to_owned(),
-        )))
-        .unwrap()
-        .into();
-        let b_u32: u32 = T::try_from(SerializedBytes::from(UnsafeBytes::from(
-            b_val[1].as_ref().to_owned(),
-        )))
-        .unwrap()
-        .into();
-        a_u32.partial_cmp(&b_u32).unwrap()
-    });
-    let latest = links.pop().unwrap();
-    Ok(latest.path)
+    leaves_needed >= frontier
+}
+
+mod search_tests {
+    #[test]
+    fn test_sort_edge_time_paths() {
+        use crate::entries::TimeIndex;
+        use crate::search::sort_edge_time_paths;
+        use crate::Order;
+        use hdk::{hash_path::path::Component, prelude::*};
+
+        let time_path = |time: u32| {
+            let component = Component::from(SerializedBytes::try_from(TimeIndex(time)).unwrap().bytes().to_owned());
+            Path::from(vec![Component::from("index"), component])
+        };
+        let paths = vec![time_path(5), time_path(12), time_path(1)];
+        assert_eq!(
+            sort_edge_time_paths(paths.clone(), &Order::Desc).unwrap(),
+            vec![time_path(12), time_path(5), time_path(1)]
+        );
+        assert_eq!(
+            sort_edge_time_paths(paths, &Order::Asc).unwrap(),
+            vec![time_path(1), time_path(5), time_path(12)]
+        );
+    }
+
+    #[test]
+    fn test_choose_search_strategy() {
+        use crate::search::choose_search_strategy;
+        use crate::{SearchStrategy, SortKey};
+        use chrono::{Duration, TimeZone, Utc};
+
+        let until = Utc.timestamp_opt(1_000, 0).unwrap();
+        let from = until + Duration::hours(1);
+        let interval = std::time::Duration::from_secs(60);
+
+        assert!(matches!(
+            choose_search_strategy(&from, &until, None, &SortKey::EntryTime, interval),
+            SearchStrategy::Bfs
+        ));
+        assert!(matches!(
+            choose_search_strategy(&from, &until, Some(5), &SortKey::LinkCreated, interval),
+            SearchStrategy::Bfs
+        ));
+        assert!(matches!(
+            choose_search_strategy(&from, &until, Some(5), &SortKey::EntryTime, interval),
+            SearchStrategy::Dfs
+        ));
+        assert!(matches!(
+            choose_search_strategy(&until, &from, Some(100), &SortKey::EntryTime, interval),
+            SearchStrategy::Bfs
+        ));
+    }
+
+    #[test]
+    fn test_should_expand_level_wise() {
+        use crate::search::{should_expand_level_wise, MAX_DFS_FRONTIER};
+
+        assert!(!should_expand_level_wise(10, 5, 0, 0));
+        assert!(!should_expand_level_wise(1, 5, 0, 3));
+        assert!(should_expand_level_wise(MAX_DFS_FRONTIER + 1, 1, 10, 1));
+        assert!(should_expand_level_wise(4, 5, 0, 1));
+        //A single empty leaf is not enough to give up on a wide frontier when only one more result is needed
+        assert!(!should_expand_level_wise(20, 1, 0, 1));
+        assert!(should_expand_level_wise(20, 1, 0, 19));
+        //One result per leaf so far and five more needed from four remaining branches
+        assert!(should_expand_level_wise(4, 5, 2, 2));
+        //Ten results per leaf so far and five more needed
+        assert!(!should_expand_level_wise(4, 5, 10, 1));
+    }
+}
+
+/// Find the oldest (Order::Asc) or newest (Order::Desc) chunk in the index by following the oldest or newest path at each level
+/// of the tree. Branches which turn out to have no chunks, such as ones which have been pruned, are backtracked out of and the next
+/// oldest or newest sibling is tried instead. If index_link_type is provided chunks whose index links have all been deleted are
+/// skipped in the same way. If not_after is provided paths and chunks starting after it are ignored. Returns None if no such chunk exists
+pub(crate) fn find_edge_chunk<ILT: LinkTypeFilterExt + Clone, PLT: Clone>(
+    index: &IndexRoot,
+    edge: &Order,
+    index_link_type: Option<ILT>,
+    not_after: Option<DateTime<Utc>>,
+    path_link_type: PLT,
+    ctx: &mut QueryContext,
+) -> IndexResult<Option<(Path, Index)>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let max_depth_size = DEFAULT_INDEX_DEPTH.len() + INDEX_DEPTH.len();
+    //Paths left to search with the next path to visit on top
+    let mut stack = vec![Path::from(vec![index.component()?])];
+    while let Some(path) = stack.pop() {
+        let children = get_children_paths_batch(std::slice::from_ref(&path), path_link_type.clone(), ctx)?
+            .pop()
+            .unwrap_or_default();
+        if path.as_ref().len() < max_depth_size {
+            let mut children = sort_edge_time_paths(children, edge)?;
+            if let Some(not_after) = not_after {
+                children = children
+                    .into_iter()
+                    .map(|child| Ok((get_time_path_start(&child)?, child)))
+                    .collect::<IndexResult<Vec<(DateTime<Utc>, Path)>>>()?
+                    .into_iter()
+                    .filter(|(start, _child)| *start <= not_after)
+                    .map(|(_start, child)| child)
+                    .collect();
+            };
+            stack.extend(children.into_iter().rev());
+            continue;
+        };
+
+        let mut chunks = children
+            .into_iter()
+            .map(|chunk_path| Ok((chunk_path.clone(), Index::try_from(chunk_path)?)))
+            .collect::<IndexResult<Vec<(Path, Index)>>>()?;
+        if let Some(not_after) = not_after {
+            chunks.retain(|(_chunk_path, index)| get_datetime_for_duration(index.from) <= not_after);
+        };
+        match edge {
+            Order::Desc => chunks.sort_by(|a, b| b.1.cmp(&a.1)),
+            Order::Asc => chunks.sort_by(|a, b| a.1.cmp(&b.1)),
+        };
+        let chunk = match &index_link_type {
+            Some(index_link_type) => {
+                let bases = chunks
+                    .iter()
+                    .map(|(chunk_path, _index)| Ok(chunk_path.path_entry_hash()?.into()))
+                    .collect::<IndexResult<Vec<AnyLinkableHash>>>()?;
+                let links = get_filtered_links_batch(bases, index_link_type.clone(), &LinkTagFilter::All, ctx)?;
+                chunks
+                    .into_iter()
+                    .zip(links)
+                    .find(|(_chunk, links)| !links.is_empty())
+                    .map(|(chunk, _links)| chunk)
+            }
+            None => chunks.into_iter().next(),
+        };
+        if chunk.is_some() {
+            return Ok(chunk);
+        };
+    }
+    Ok(None)
 }
@@ -0,0 +1,54 @@
+use hdk::prelude::*;
+
+use crate::errors::IndexResult;
+use crate::fetch::get_filtered_link_details_batch;
+use crate::query::QueryContext;
+use crate::subscribers::get_subscribers;
+use crate::{IndexSignal, LinkTagFilter, SignalRecipients};
+
+/// Send index_signal to recipients with remote_signal. For SignalRecipients::ChunkAuthors the authors of the index links in the
+/// chunk at chunk_path are found using get_link_details, leaving out links which have been deleted
+pub(crate) fn send_index_signal<PLT: Clone>(
+    index_signal: IndexSignal,
+    recipients: SignalRecipients,
+    chunk_path: &Path,
+    ctx: &mut QueryContext,
+    index_link_type: impl LinkTypeFilterExt + Clone,
+    path_link_type: PLT,
+) -> IndexResult<()>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let mut agents = match recipients {
+        SignalRecipients::Agents(agents) => agents,
+        SignalRecipients::ChunkAuthors => get_filtered_link_details_batch(
+            vec![chunk_path.path_entry_hash()?.into()],
+            index_link_type,
+            &LinkTagFilter::All,
+            ctx,
+        )?
+        .into_iter()
+        .flatten()
+        .filter(|(_create, deletes)| deletes.is_empty())
+        .map(|(create, _deletes)| create.action().author().clone())
+        .collect(),
+        SignalRecipients::Subscribers(ttl) => get_subscribers(
+            index_signal.index.clone(),
+            ttl,
+            None,
+            ctx,
+            index_link_type,
+            path_link_type,
+        )?
+        .results,
+    };
+    let me = agent_info()?.agent_latest_pubkey;
+    agents.retain(|agent| *agent != me);
+    agents.sort();
+    agents.dedup();
+    if agents.is_empty() {
+        return Ok(());
+    };
+    remote_signal(index_signal, agents)?;
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+use hdk::prelude::*;
+
+/// Known children of paths in a time tree. A snapshot can be attached to [`crate::QueryOptions`] and reused by later searches
+/// within the same zome call, or serialized and sent back with a later call, so that the year/month/day paths already walked by
+/// earlier searches are not fetched again.
+/// Children added to the tree after a path was recorded will not be seen by searches using the snapshot. Use
+/// [`TimeTreeSnapshot::forget()`] or [`TimeTreeSnapshot::clear()`] for paths which may have changed
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TimeTreeSnapshot {
+    children: BTreeMap<Vec<Vec<u8>>, Vec<Path>>,
+}
+
+fn get_key(path: &Path) -> Vec<Vec<u8>> {
+    path.as_ref()
+        .iter()
+        .map(|component| component.as_ref().to_vec())
+        .collect()
+}
+
+impl TimeTreeSnapshot {
+    pub fn new() -> TimeTreeSnapshot {
+        TimeTreeSnapshot::default()
+    }
+
+    /// Known children of path. None if the children of path have not been recorded
+    pub fn get_children(&self, path: &Path) -> Option<&Vec<Path>> {
+        self.children.get(&get_key(path))
+    }
+
+    /// Number of paths whose children are known
+    pub fn len(&self) -> usize {
+        self.children.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Forget the children of path and of every path below it so they are fetched again by the next search
+    pub fn forget(&mut self, path: &Path) {
+        let key = get_key(path);
+        self.children.retain(|known, _children| !known.starts_with(&key));
+    }
+
+    /// Forget the children of all paths
+    pub fn clear(&mut self) {
+        self.children.clear();
+    }
+
+    pub(crate) fn insert(&mut self, path: &Path, children: Vec<Path>) {
+        self.children.insert(get_key(path), children);
+    }
+}
+
+mod snapshot_tests {
+    #[test]
+    fn test_snapshot() {
+        use crate::snapshot::TimeTreeSnapshot;
+        use hdk::prelude::*;
+
+        let mut snapshot = TimeTreeSnapshot::new();
+        snapshot.insert(&Path::from("index"), vec![Path::from("index.2021")]);
+        snapshot.insert(&Path::from("index.2021"), vec![Path::from("index.2021.1")]);
+        snapshot.insert(&Path::from("other"), vec![]);
+
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(
+            snapshot.get_children(&Path::from("index.2021")),
+            Some(&vec![Path::from("index.2021.1")])
+        );
+        assert_eq!(snapshot.get_children(&Path::from("other")), Some(&vec![]));
+        assert_eq!(snapshot.get_children(&Path::from("index.2022")), None);
+
+        snapshot.forget(&Path::from("index"));
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot.get_children(&Path::from("index.2021")), None);
+        snapshot.clear();
+        assert!(snapshot.is_empty());
+    }
+}
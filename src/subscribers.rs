@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hdk::prelude::*;
+
+use crate::entries::IndexRoot;
+use crate::errors::{IndexError, IndexResult};
+use crate::fetch::{get_filtered_link_details_batch, LinkWithDeletes};
+use crate::methods::{create_for_timestamp, find_affordable_chunks};
+use crate::query::QueryContext;
+use crate::utils::get_datetime_for_timestamp;
+use crate::{Bound, LinkTagFilter, Order, Page, TimeRange};
+
+fn subscriber_tag() -> LinkTag {
+    LinkTag::new("subscriber")
+}
+
+/// Earliest time a registration can have been made at and still be active
+fn get_active_from(ttl: Duration) -> IndexResult<DateTime<Utc>> {
+    let now = get_datetime_for_timestamp(sys_time()?).ok_or(IndexError::InternalError("Should not overflow"))?;
+    let ttl = chrono::Duration::from_std(ttl)
+        .map_err(|_| IndexError::RequestError("Subscription ttl is too large"))?;
+    Ok(now - ttl)
+}
+
+/// Find the registrations for index filed inside of range, along with any deletes of them. Only as many chunks of registrations
+/// are read as the budget allows, in which case the page continues from the last chunk read
+fn get_registrations<PLT: Clone>(
+    index: String,
+    range: TimeRange,
+    ctx: &mut QueryContext,
+    index_link_type: impl LinkTypeFilterExt,
+    path_link_type: PLT,
+) -> IndexResult<Page<LinkWithDeletes>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let Page {
+        results: chunks,
+        continuation,
+    } = find_affordable_chunks(range, IndexRoot::Subscribers(index), ctx, path_link_type)?;
+    let chunk_hashes = chunks
+        .iter()
+        .map(|(chunk_path, _chunk)| Ok(chunk_path.path_entry_hash()?.into()))
+        .collect::<IndexResult<Vec<AnyLinkableHash>>>()?;
+    let registrations = get_filtered_link_details_batch(
+        chunk_hashes,
+        index_link_type,
+        &LinkTagFilter::Exact(subscriber_tag()),
+        ctx,
+    )?
+    .into_iter()
+    .flatten()
+    .collect();
+    Ok(Page {
+        results: registrations,
+        continuation,
+    })
+}
+
+/// Get the agent a live registration was made for. Agents can only register themselves, so registrations whose target is not
+/// the key of the agent who made them are ignored, as are registrations whose target is not an agent at all
+fn get_registered_agent((create, deletes): &LinkWithDeletes) -> Option<AgentPubKey> {
+    if !deletes.is_empty() {
+        return None;
+    };
+    let target = match create.action() {
+        Action::CreateLink(create_link) => create_link.target_address.clone(),
+        _ => return None,
+    };
+    //An agent key is retyped as an entry hash when it is used as a link target
+    let agent = AgentPubKey::from(target.into_entry_hash()?);
+    if agent == *create.action().author() {
+        Some(agent)
+    } else {
+        None
+    }
+}
+
+/// Register the calling agent as a subscriber of index by linking the chunk covering the current time to the agent. Subscribing
+/// again while that chunk already holds a live registration by the agent does nothing
+pub(crate) fn subscribe<ILT: LinkTypeFilterExt + Clone, PLT>(
+    index: String,
+    index_link_type: ILT,
+    path_link_type: PLT,
+) -> IndexResult<()>
+where
+    ScopedLinkType: TryFrom<ILT, Error = WasmError> + TryFrom<PLT, Error = WasmError>,
+{
+    let now = get_datetime_for_timestamp(sys_time()?).ok_or(IndexError::InternalError("Should not overflow"))?;
+    let chunk = create_for_timestamp(&IndexRoot::Subscribers(index), now, path_link_type)?;
+    let me = agent_info()?.agent_latest_pubkey;
+    let registered = get_filtered_link_details_batch(
+        vec![chunk.path_entry_hash()?.into()],
+        index_link_type.clone(),
+        &LinkTagFilter::Exact(subscriber_tag()),
+        &mut QueryContext::default(),
+    )?
+    .iter()
+    .flatten()
+    .any(|registration| get_registered_agent(registration).as_ref() == Some(&me));
+    if !registered {
+        create_link(chunk.path_entry_hash()?, me, index_link_type, subscriber_tag())?;
+    };
+    Ok(())
+}
+
+/// Delete the calling agent's registrations for index made within ttl. Returns the number of registrations removed
+pub(crate) fn unsubscribe<PLT: Clone>(
+    index: String,
+    ttl: Duration,
+    ctx: &mut QueryContext,
+    index_link_type: impl LinkTypeFilterExt,
+    path_link_type: PLT,
+) -> IndexResult<usize>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let me = agent_info()?.agent_latest_pubkey;
+    let mut removed = 0;
+    let range = TimeRange::between(Bound::Included(get_active_from(ttl)?), Bound::Unbounded, Order::Asc);
+    for (create, deletes) in get_registrations(index, range, ctx, index_link_type, path_link_type)?.results {
+        if *create.action().author() == me && deletes.is_empty() {
+            delete_link(create.action_address().clone())?;
+            removed += 1;
+        };
+    }
+    Ok(removed)
+}
+
+/// Get the agents with a registration for index made within ttl, most recently registered first. Registrations are read from
+/// resume_from, or from the current time, back to ttl ago. If the budget does not allow every chunk of registrations to be read the
+/// page holds the agents found so far and continues from the last chunk read
+pub(crate) fn get_subscribers<PLT: Clone>(
+    index: String,
+    ttl: Duration,
+    resume_from: Option<DateTime<Utc>>,
+    ctx: &mut QueryContext,
+    index_link_type: impl LinkTypeFilterExt,
+    path_link_type: PLT,
+) -> IndexResult<Page<AgentPubKey>>
+where
+    ScopedLinkType: TryFrom<PLT, Error = WasmError>,
+{
+    let active_from = get_active_from(ttl)?;
+    let range = TimeRange::between(Bound::Included(active_from), Bound::Unbounded, Order::Desc);
+    let range = match resume_from {
+        Some(resume_from) => range.resume_from(resume_from),
+        None => range,
+    };
+    let Page {
+        results: mut registrations,
+        continuation,
+    } = get_registrations(index, range, ctx, index_link_type, path_link_type)?;
+    registrations.sort_by_key(|(create, _deletes)| std::cmp::Reverse(create.action().timestamp()));
+    //The chunk active_from falls in can hold registrations made before it
+    let mut subscribers: Vec<AgentPubKey> = vec![];
+    for registration in registrations {
+        let registered_at = get_datetime_for_timestamp(registration.0.action().timestamp())
+            .ok_or(IndexError::InternalError("Should not overflow"))?;
+        match get_registered_agent(&registration) {
+            Some(agent) if registered_at >= active_from && !subscribers.contains(&agent) => subscribers.push(agent),
+            _ => (),
+        };
+    }
+    Ok(Page {
+        results: subscribers,
+        continuation,
+    })
+}
@@ -1,10 +1,10 @@
-use chrono::{DateTime, Datelike, NaiveDateTime, Timelike, Utc};
+use chrono::{DateTime, Datelike, NaiveDateTime, TimeZone, Timelike, Utc};
 use hdk::{hash_path::path::Component, prelude::*};
 //use hdi::prelude::Timestamp;
 
-use crate::entries::{Index, IndexType, StringIndex, TimeIndex};
-use crate::errors::{IndexResult};
-use crate::{INDEX_DEPTH, MAX_CHUNK_INTERVAL};
+use crate::entries::{Index, IndexRoot, IndexType, TimeIndex};
+use crate::errors::{IndexError, IndexResult};
+use crate::{Order, SortKey, INDEX_DEPTH, MAX_CHUNK_INTERVAL};
 
 /// Find the overlapping path between two times and return vec of queries at given IndexTypes which still need to be performed
 pub(crate) fn find_divergent_time(
@@ -109,16 +109,14 @@ pub(crate) fn find_divergent_time(
 
 /// Create a timestamp path tree from a given duration and index
 pub(crate) fn get_time_path(
-    index: String,
+    index: &IndexRoot,
     from: std::time::Duration,
 ) -> IndexResult<Vec<Component>> {
     let from_timestamp = DateTime::<Utc>::from_utc(
         NaiveDateTime::from_timestamp(from.as_secs_f64() as i64, from.subsec_nanos()),
         Utc,
     );
-    let mut time_path = vec![Component::from(
-        StringIndex(index).get_sb()?.bytes().to_owned(),
-    )];
+    let mut time_path = vec![index.component()?];
     add_time_index_to_path::<TimeIndex>(&mut time_path, &from_timestamp, IndexType::Year)?;
     add_time_index_to_path::<TimeIndex>(&mut time_path, &from_timestamp, IndexType::Month)?;
     add_time_index_to_path::<TimeIndex>(&mut time_path, &from_timestamp, IndexType::Day)?;
@@ -187,11 +185,104 @@ pub(crate) fn get_index_for_timestamp(time: DateTime<Utc>) -> Index {
     }
 }
 
+/// Convert a duration since UNIX epoch, as used by [`Index`], into a DateTime
+pub(crate) fn get_datetime_for_duration(time: std::time::Duration) -> DateTime<Utc> {
+    Utc.timestamp_opt(time.as_secs() as i64, time.subsec_nanos())
+        .unwrap()
+}
+
+/// Convert a holochain timestamp into a DateTime
+pub(crate) fn get_datetime_for_timestamp(time: Timestamp) -> Option<DateTime<Utc>> {
+    let (secs, nanos) = time.as_seconds_and_nanos();
+    Utc.timestamp_opt(secs, nanos).single()
+}
+
+/// Start of the time period covered by a time path below the index root. Components missing from the end of the path are taken
+/// to be at the start of their period
+pub(crate) fn get_time_path_start(path: &Path) -> IndexResult<DateTime<Utc>> {
+    let times = path
+        .as_ref()
+        .iter()
+        .skip(1)
+        .map(|component| Ok(TimeIndex::try_from(component.clone())?.0))
+        .collect::<IndexResult<Vec<u32>>>()?;
+    let time = |position: usize, default: u32| times.get(position).copied().unwrap_or(default);
+    Utc.with_ymd_and_hms(time(0, 1970) as i32, time(1, 1), time(2, 1), time(3, 0), time(4, 0), time(5, 0))
+        .single()
+        .ok_or(IndexError::InternalError("Time path does not describe a valid time"))
+}
+
+/// Get the current time from sys_time moved forward by max_future_skew. Time paths starting after this are taken to have
+/// been created by an agent with a skewed clock
+pub(crate) fn get_latest_allowed_time(max_future_skew: std::time::Duration) -> IndexResult<DateTime<Utc>> {
+    let now = get_datetime_for_timestamp(sys_time()?)
+        .ok_or(IndexError::InternalError("Should not overflow"))?;
+    Ok(now + chrono::Duration::from_std(max_future_skew).map_err(|_err| IndexError::InternalError("Should not overflow"))?)
+}
+
+/// Check if time is inside of the from & until bounds of a search. Bounds are inclusive and from is greater than until for Order::Desc
+pub(crate) fn is_within_bounds(
+    time: &DateTime<Utc>,
+    from: &DateTime<Utc>,
+    until: &DateTime<Utc>,
+    order: &Order,
+) -> bool {
+    match order {
+        Order::Desc => time <= from && time >= until,
+        Order::Asc => time >= from && time <= until,
+    }
+}
+
+/// Check if chunk overlaps the from & until bounds of a search. A chunk covers the time from its start up to its end, so a search
+/// continuing from the end of a chunk does not read it again
+pub(crate) fn is_chunk_within_bounds(chunk: &Index, from: &DateTime<Utc>, until: &DateTime<Utc>, order: &Order) -> bool {
+    let (lower, upper) = match order {
+        Order::Desc => (until, from),
+        Order::Asc => (from, until),
+    };
+    get_datetime_for_duration(chunk.from) <= *upper && get_datetime_for_duration(chunk.until) > *lower
+}
+
+/// Check if a link found in index is inside of the from & until bounds of a search using sort_key.
+/// For SortKey::EntryTime the link is inside of the bounds when its chunk overlaps them
+pub(crate) fn is_link_within_bounds(
+    link: &Link,
+    index: &Index,
+    sort_key: &SortKey,
+    from: &DateTime<Utc>,
+    until: &DateTime<Utc>,
+    order: &Order,
+) -> bool {
+    match sort_key {
+        SortKey::EntryTime => is_chunk_within_bounds(index, from, until, order),
+        _ => sort_key
+            .link_time(link, index)
+            .map(|time| is_within_bounds(&time, from, until, order))
+            .unwrap_or(false),
+    }
+}
+
 // pub fn get_now() -> IndexResult<Timestamp> {
 //     Ok(sys_time()?)
 // }
 
 mod util_tests {
+    #[test]
+    fn test_get_time_path_start() {
+        use crate::entries::TimeIndex;
+        use crate::utils::get_time_path_start;
+        use chrono::{TimeZone, Utc};
+        use hdk::{hash_path::path::Component, prelude::*};
+
+        let component = |time: u32| Component::from(SerializedBytes::try_from(TimeIndex(time)).unwrap().bytes().to_owned());
+        let year = Path::from(vec![Component::from("index"), component(2022)]);
+        assert_eq!(get_time_path_start(&year).unwrap(), Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap());
+        let day = Path::from(vec![Component::from("index"), component(2022), component(3), component(14)]);
+        assert_eq!(get_time_path_start(&day).unwrap(), Utc.with_ymd_and_hms(2022, 3, 14, 0, 0, 0).unwrap());
+        let invalid = Path::from(vec![Component::from("index"), component(2022), component(13)]);
+        assert!(get_time_path_start(&invalid).is_err());
+    }
+
     #[test]
     fn test_get_chunk_time() {
         use crate::utils::get_index_for_timestamp;
@@ -203,6 +294,59 @@ mod util_tests {
         assert_eq!(chunk.until.as_secs() % interval, 0);
     }
 
+    #[test]
+    fn test_chunk_within_bounds() {
+        use crate::entries::Index;
+        use crate::utils::is_chunk_within_bounds;
+        use crate::Order;
+        use chrono::{Duration, TimeZone, Utc};
+
+        let chunk = Index {
+            from: std::time::Duration::from_secs(100),
+            until: std::time::Duration::from_secs(110),
+        };
+        let start = Utc.timestamp_opt(100, 0).unwrap();
+        let end = Utc.timestamp_opt(110, 0).unwrap();
+        assert!(is_chunk_within_bounds(&chunk, &start, &end, &Order::Asc));
+        //Continuations from the chunk exclude it
+        assert!(!is_chunk_within_bounds(&chunk, &end, &Utc.timestamp_opt(200, 0).unwrap(), &Order::Asc));
+        let before_start = start - Duration::nanoseconds(1);
+        assert!(!is_chunk_within_bounds(&chunk, &before_start, &Utc.timestamp_opt(0, 0).unwrap(), &Order::Desc));
+        assert!(is_chunk_within_bounds(&chunk, &start, &Utc.timestamp_opt(0, 0).unwrap(), &Order::Desc));
+    }
+
+    #[test]
+    fn test_link_within_bounds() {
+        use crate::entries::Index;
+        use crate::utils::is_link_within_bounds;
+        use crate::{Order, SortKey};
+        use chrono::{TimeZone, Utc};
+        use hdk::prelude::*;
+        use std::time::Duration;
+
+        let link = Link {
+            target: EntryHash::from_raw_36(vec![0; 36]).into(),
+            timestamp: Timestamp::from_micros(500_000_000),
+            zome_index: ZomeIndex::new(0),
+            link_type: LinkType::new(0),
+            tag: LinkTag::new("test"),
+            create_link_hash: ActionHash::from_raw_36(vec![0; 36]),
+        };
+        let index = Index {
+            from: Duration::from_secs(100),
+            until: Duration::from_secs(110),
+        };
+        let from = Utc.timestamp_opt(105, 0).unwrap();
+        let until = Utc.timestamp_opt(200, 0).unwrap();
+
+        //Chunk overlaps bounds but link was created after them
+        assert!(is_link_within_bounds(&link, &index, &SortKey::EntryTime, &from, &until, &Order::Asc));
+        assert!(is_link_within_bounds(&link, &index, &SortKey::EntryTime, &until, &from, &Order::Desc));
+        assert!(!is_link_within_bounds(&link, &index, &SortKey::LinkCreated, &from, &until, &Order::Asc));
+        let later = Utc.timestamp_opt(110, 0).unwrap();
+        assert!(!is_link_within_bounds(&link, &index, &SortKey::EntryTime, &later, &until, &Order::Asc));
+    }
+
     #[test]
     fn translate_sort() {
         let str_nums = vec!["2", "1"];
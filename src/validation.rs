@@ -1,22 +1,17 @@
+use std::time::Duration;
+
 use hdk::prelude::*;
 
 use crate::entries::Index;
 use crate::errors::{IndexError, IndexResult};
-use crate::MAX_CHUNK_INTERVAL;
+use crate::{MAX_CHUNK_INTERVAL, MAX_FUTURE_SKEW};
 
 impl Index {
-    pub fn validate_chunk(&self) -> IndexResult<()> {
+    /// Check that the chunk follows the chunk interval of the DNA and does not start more than MAX_FUTURE_SKEW after created_at,
+    /// the timestamp of the action which created the chunk or linked to it. sys_time is not used so that validation is deterministic
+    pub fn validate_chunk(&self, created_at: Timestamp) -> IndexResult<()> {
         //TODO: incorrect error type being used here
-        let now_since_epoch = sys_time()?
-            .checked_difference_signed(&Timestamp::from_micros(0))
-            .ok_or(IndexError::InternalError("Should not overflow"))?
-            .to_std()
-            .map_err(|_err| IndexError::InternalError("Should not overflow"))?;
-        if self.from > now_since_epoch {
-            return Err(IndexError::RequestError(
-                "Time chunk cannot start in the future",
-            ));
-        };
+        self.validate_chunk_time(created_at, *MAX_FUTURE_SKEW)?;
         if self.until - self.from != *MAX_CHUNK_INTERVAL {
             return Err(IndexError::RequestError(
                 "Time chunk should use period equal to max interval set by DNA",
@@ -35,6 +30,22 @@ impl Index {
         Ok(())
     }
 
+    /// Check that the chunk does not start more than max_future_skew after created_at, the time of the action which linked to it.
+    /// The action's timestamp is used rather than sys_time so that the author and every validator reach the same result
+    pub fn validate_chunk_time(&self, created_at: Timestamp, max_future_skew: Duration) -> IndexResult<()> {
+        let created_since_epoch = created_at
+            .checked_difference_signed(&Timestamp::from_micros(0))
+            .ok_or(IndexError::InternalError("Should not overflow"))?
+            .to_std()
+            .map_err(|_err| IndexError::InternalError("Should not overflow"))?;
+        if self.from > created_since_epoch + max_future_skew {
+            return Err(IndexError::RequestError(
+                "Time chunk cannot start in the future",
+            ));
+        };
+        Ok(())
+    }
+
     // pub fn validate_chunk_link(&self, link: LinkData) -> ExternResult<()> {
     //     //Interesting interplay developing here
     //     //The complexity to make one link increases with number of links on that chunk
@@ -49,3 +60,68 @@ impl Index {
     //     }
     // }
 }
+
+mod validation_tests {
+    #[test]
+    fn test_validate_chunk_time() {
+        use crate::entries::Index;
+        use crate::IndexConfiguration;
+        use hdk::prelude::*;
+        use std::time::Duration;
+
+        let chunk = Index {
+            from: Duration::from_secs(10),
+            until: Duration::from_secs(11),
+        };
+        let created_at = Timestamp::from_micros(9_500_000);
+        assert!(chunk.validate_chunk_time(created_at, Duration::from_millis(0)).is_err());
+        assert!(chunk.validate_chunk_time(created_at, Duration::from_millis(500)).is_ok());
+        assert!(chunk.validate_chunk_time(Timestamp::from_micros(10_000_000), Duration::from_millis(0)).is_ok());
+
+        #[derive(Serialize, Deserialize, Debug, SerializedBytes)]
+        struct Properties {
+            enforce_spam_limit: usize,
+            max_chunk_interval: usize,
+        }
+        let properties = SerializedBytes::try_from(Properties {
+            enforce_spam_limit: 20,
+            max_chunk_interval: 1000,
+        })
+        .unwrap();
+        let configuration = IndexConfiguration::try_from(properties).unwrap();
+        assert_eq!(configuration.max_future_skew_ms, 0);
+        assert!(!configuration.ingest_log);
+    }
+
+    #[test]
+    fn test_validate_chunk_link_tags() {
+        use crate::entries::{IndexRoot, TimeIndex};
+        use crate::validate_chunk_link;
+        use hdk::prelude::*;
+
+        let link = |tag: Vec<u8>| CreateLink {
+            author: AgentPubKey::from_raw_36(vec![0; 36]),
+            timestamp: Timestamp::from_micros(0),
+            action_seq: 0,
+            prev_action: ActionHash::from_raw_36(vec![0; 36]),
+            base_address: EntryHash::from_raw_36(vec![0; 36]).into(),
+            target_address: EntryHash::from_raw_36(vec![1; 36]).into(),
+            zome_index: 0.into(),
+            link_type: 0.into(),
+            tag: LinkTag::new(tag),
+            weight: RateWeight::default(),
+        };
+        let path_tag = |component: hdk::hash_path::path::Component| {
+            UnsafeBytes::from(SerializedBytes::try_from(component).unwrap()).into()
+        };
+        let time = TimeIndex(2021).get_sb().unwrap().bytes().to_owned();
+        let root = IndexRoot::Index(String::from("posts")).component().unwrap();
+        for tag in [path_tag(time.into()), path_tag(root)] {
+            assert_eq!(validate_chunk_link(&link(tag)).unwrap(), ValidateCallbackResult::Valid);
+        }
+        //Tags which do not decode to a time path component could hide a chunk and are rejected
+        for tag in [vec![0xc1], path_tag(vec![0xc1, 0xc1].into())] {
+            assert!(matches!(validate_chunk_link(&link(tag)).unwrap(), ValidateCallbackResult::Invalid(_)));
+        }
+    }
+}
@@ -1,26 +1,61 @@
-import { Scenario, runScenario } from '@holochain/tryorama'
+import { Player, Scenario, runScenario } from '@holochain/tryorama'
 import path from 'path'
 import test from "tape-promise/tape";
 
 const dnas = [{ path: path.join("../workdir/time-index-test.dna") }];
+//Same zomes with ingest_log enabled and a max_future_skew_ms of one second
+const ingestLogDnas = [{ path: path.join("../workdir-ingest-log/time-index-test-ingest-log.dna") }];
 
 const now = new Date("August 12, 2021 14:01:30")
+const yesterday = daysAgo(1)
+
+//Time the given number of days before from, which is the fixed now used by most tests unless a test passes the current time
+function daysAgo(days: number, from: Date = now): Date {
+  return new Date(from.getTime() - days * 24*60*60*1000)
+}
+
+//Call fn_name of the test zome as player and log the result
+async function callTestZome(player: Player, fn_name: string, payload: any): Promise<any> {
+  const result = await player.cells[0].callZome({
+    zome_name: "test_zome",
+    fn_name,
+    payload
+  })
+  console.log(`Got ${fn_name}`, result);
+  return result
+}
+
+//Time in milliseconds of a Duration returned by the zome
+function durationMs(duration: {secs: number, nanos: number}): number {
+  return duration.secs * 1000 + duration.nanos / 1000000
+}
+
+//Start and end in milliseconds of the chunk covering time, for chunks interval milliseconds long
+function chunkMs(time: Date, interval: number): {from: number, until: number} {
+  const from = Math.floor(time.getTime() / interval) * interval
+  return {from, until: from + interval}
+}
+
+//Index an entry with each of the titles at the time paired with it, one after the other
+async function indexEntries(player: Player, entries: [string, Date][]) {
+  for (const [title, created] of entries) {
+    await callTestZome(player, "index_entry", {title, created: created.toISOString()})
+  }
+}
+
+//Index "A test index" at now and "A test index2" a day before it, which most tests start from
+async function indexTestEntries(player: Player) {
+  await indexEntries(player, [["A test index", now], ["A test index2", yesterday]])
+}
 
 test("test get empty path", async (t) => {
   await runScenario(async (scenario: Scenario) => {
     const [alice] = await scenario.addPlayersWithHapps([dnas]);
     console.log("Init alice happ");
 
-    var dateOffset = (24*60*60*1000); //1 day ago
-    var date = new Date();
-    date.setTime(date.getTime() - dateOffset);
+    var date = daysAgo(1, new Date());
 
-    let results_between = await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "get_links_for_time_span", 
-      payload: {index: "test_index", from: new Date().toISOString(), until: date.toISOString(), limit: 10}
-    })
-    console.log("Got results", results_between);
+    let results_between = await callTestZome(alice, "get_links_for_time_span", {index: "test_index", from: new Date().toISOString(), until: date.toISOString(), limit: 10})
     //@ts-ignore
     t.equal(results_between.length, 0)
 
@@ -32,64 +67,23 @@ test("test get index dfs", async (t) => {
   await runScenario(async (scenario: Scenario) => {
     const [alice] = await scenario.addPlayersWithHapps([dnas]);
 
-    var dateOffset = (24*60*60*1000); //1 day ago
-    var yesterday = new Date();
-    yesterday.setTime(yesterday.getTime() - dateOffset);
+    var yesterday = daysAgo(1, new Date());
 
-    var dateOffset = (24*60*60*1000) * 2; //1 day ago
-    var twoDaysAgo = new Date();
-    twoDaysAgo.setTime(twoDaysAgo.getTime() - dateOffset);
+    var twoDaysAgo = daysAgo(2, new Date());
 
-    var dateOffset = (24*60*60*1000) * 3; //1 day ago
-    var threeDaysAgo = new Date();
-    threeDaysAgo.setTime(threeDaysAgo.getTime() - dateOffset);
+    var threeDaysAgo = daysAgo(3, new Date());
 
-    var dateOffset = (24*60*60*1000) * 4; //1 day ago
-    var fourDaysAgo = new Date();
-    fourDaysAgo.setTime(fourDaysAgo.getTime() - dateOffset);
+    var fourDaysAgo = daysAgo(4, new Date());
 
-    var dateOffset = (24*60*60*1000) * 60; //1 day ago
-    var twoMonthsAgo = new Date();
-    twoMonthsAgo.setTime(twoMonthsAgo.getTime() - dateOffset);
+    var twoMonthsAgo = daysAgo(60, new Date());
 
     //Index entry
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry", 
-      payload: {title: "A test index", created: new Date().toISOString()}
-    })
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry", 
-      payload: {title: "A test index2", created: yesterday.toISOString()}
-    })
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry", 
-      payload: {title: "A test index3", created: twoDaysAgo.toISOString()}
-    })
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry", 
-      payload: {title: "A test index4", created: threeDaysAgo.toISOString()}
-    })
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry", 
-      payload: {title: "A test index5", created: twoMonthsAgo.toISOString()}
-    })
-
-    var dateOffset = (24*60*60*1000); //1 day ago
-    var date = new Date();
-    date.setTime(date.getTime() - dateOffset);
+    await indexEntries(alice, [["A test index", new Date()], ["A test index2", yesterday], ["A test index3", twoDaysAgo], ["A test index4", threeDaysAgo], ["A test index5", twoMonthsAgo]])
+
+    var date = daysAgo(1, new Date());
 
     //Get results in descending order
-    let results_between = await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "get_links_for_time_span", 
-      payload: {index: "test_index", from: new Date().toISOString(), until: twoMonthsAgo.toISOString(), limit: 10}
-    })
-    console.log("Got results", results_between);
+    let results_between = await callTestZome(alice, "get_links_for_time_span", {index: "test_index", from: new Date().toISOString(), until: twoMonthsAgo.toISOString(), limit: 10})
     //@ts-ignore
     t.equal(results_between.length, 5)
     //@ts-ignore
@@ -100,13 +94,20 @@ test("test get index dfs", async (t) => {
       }
     }
 
+    //Get results in descending order of entry time. Entries were indexed newest first so newest entry time is oldest link creation
+    let by_entry_time = await callTestZome(alice, "get_links_by_entry_time_for_time_span", {index: "test_index", from: new Date().toISOString(), until: twoMonthsAgo.toISOString(), limit: 10})
+    //@ts-ignore
+    t.equal(by_entry_time.length, 5)
+    //@ts-ignore
+    for (let i=0; i < by_entry_time.length; i++) {
+      if (i != 0) {
+        //@ts-ignore
+        t.assert(by_entry_time[i].timestamp > by_entry_time[i-1].timestamp)
+      }
+    }
+
     //Get results in ascending order
-    let asc_results = await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "get_links_for_time_span", 
-      payload: {index: "test_index", from: twoMonthsAgo.toISOString(), until: new Date().toISOString(), limit: 10}
-    })
-    console.log("Got results", asc_results);
+    let asc_results = await callTestZome(alice, "get_links_for_time_span", {index: "test_index", from: twoMonthsAgo.toISOString(), until: new Date().toISOString(), limit: 10})
     //@ts-ignore
     t.equal(asc_results.length, 5)
 
@@ -114,59 +115,40 @@ test("test get index dfs", async (t) => {
   })
 })
 
-test("test get links and load dfs", async (t) => {
+test("test backdated links are found in the range they were indexed under", async (t) => {
   await runScenario(async (scenario: Scenario) => {
     const [alice] = await scenario.addPlayersWithHapps([dnas]);
 
-    var dateOffset = (24*60*60*1000); //1 day ago
-    var yesterday = new Date(now.getTime() - dateOffset);
+    //Entry imported today into last month
+    var lastMonth = new Date(now.getTime() - (30*24*60*60*1000));
+    await indexEntries(alice, [["A test index", lastMonth]])
 
-    var dateOffset = (24*60*60*1000) * 2; //2 day ago
-    var twoDaysAgo = new Date(now.getTime() - dateOffset);
+    //The link is ordered by when it was created but still found in a range which only covers last month
+    let links = await callTestZome(alice, "get_links_for_time_span", {index: "test_index", from: new Date(lastMonth.getTime() - (60*60*1000)).toISOString(), until: new Date(lastMonth.getTime() + (60*60*1000)).toISOString(), limit: 10})
+    //@ts-ignore
+    t.equal(links.length, 1)
+
+    await scenario.cleanUp()
+  })
+})
 
-    var dateOffset = (24*60*60*1000) * 3; //3 day ago
-    var threeDaysAgo = new Date(now.getTime() - dateOffset);
+test("test get links and load dfs", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
 
-    var dateOffset = (24*60*60*1000) * 4; //4 day ago
-    var fourDaysAgo = new Date(now.getTime() - dateOffset);
+    var twoDaysAgo = daysAgo(2);
 
-    var dateOffset = (24*60*60*1000) * 60; //2 months ago
-    var twoMonthsAgo = new Date(now.getTime() - dateOffset);
+    var threeDaysAgo = daysAgo(3);
+
+    var fourDaysAgo = daysAgo(4);
+
+    var twoMonthsAgo = daysAgo(60);
 
     //Index entry
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry",
-      payload: {title: "A test index", created: now.toISOString()}
-    })
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry",
-      payload: {title: "A test index2", created: yesterday.toISOString()}
-    })
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry",
-      payload: {title: "A test index3", created: twoDaysAgo.toISOString()}
-    })
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry",
-      payload: {title: "A test index4", created: threeDaysAgo.toISOString()}
-    })
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry",
-      payload: {title: "A test index5", created: twoMonthsAgo.toISOString()}
-    })
+    await indexEntries(alice, [["A test index", now], ["A test index2", yesterday], ["A test index3", twoDaysAgo], ["A test index4", threeDaysAgo], ["A test index5", twoMonthsAgo]])
 
     //Get results in descending order
-    let results_between = await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "get_links_and_load_for_time_span",
-      payload: {index: "test_index", from: now.toISOString(), until: twoMonthsAgo.toISOString(), limit: 10}
-    })
-    console.log("Got results", results_between);
+    let results_between = await callTestZome(alice, "get_links_and_load_for_time_span", {index: "test_index", from: now.toISOString(), until: twoMonthsAgo.toISOString(), limit: 10})
     //@ts-ignore
     t.equal(results_between.length, 5)
     //@ts-ignore
@@ -181,12 +163,7 @@ test("test get links and load dfs", async (t) => {
     t.equal(results_between[4].title, "A test index5")
 
     //Get results in ascending order
-    let asc_results = await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "get_links_and_load_for_time_span",
-      payload: {index: "test_index", from: twoMonthsAgo.toISOString(), until: now.toISOString(), limit: 10}
-    })
-    console.log("Got results", asc_results);
+    let asc_results = await callTestZome(alice, "get_links_and_load_for_time_span", {index: "test_index", from: twoMonthsAgo.toISOString(), until: now.toISOString(), limit: 10})
     //@ts-ignore
     t.equal(asc_results.length, 5)
     //@ts-ignore
@@ -208,52 +185,23 @@ test("test simple index", async (t) => {
   await runScenario(async (scenario: Scenario) => {
     const [alice] = await scenario.addPlayersWithHapps([dnas]);
 
-    var dateOffset = 10; //10ms
-    var date = new Date();
-    date.setTime(date.getTime() - dateOffset);
+    var date = new Date(new Date().getTime() - 10); //10ms ago
 
     //Index entry
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry",
-      payload: {title: "A test index", created: new Date().toISOString()}
-    })
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry",
-      payload: {title: "A test index2", created: date.toISOString()}
-    })
+    await indexEntries(alice, [["A test index", new Date()], ["A test index2", date]])
 
     //Create another index for one day ago
-    var dateOffset = (24*60*60*1000); //1 day ago
-    var date = new Date();
-    date.setTime(date.getTime() - dateOffset);
-    await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "index_entry",
-      payload: {title: "A test index3", created: date.toISOString()}
-    })
-
-    let results_between = await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "get_indexes_for_time_span",
-      payload: {index: "test_index", from: date.toISOString(), until: new Date().toISOString(), limit: 10}
-    })
-    console.log("Got results between", results_between);
+    var date = daysAgo(1, new Date());
+    await indexEntries(alice, [["A test index3", date]])
+
+    let results_between = await callTestZome(alice, "get_indexes_for_time_span", {index: "test_index", from: date.toISOString(), until: new Date().toISOString(), limit: 10})
     //@ts-ignore
     t.deepEqual(results_between.length, 2);
 
     //Create another index for one day ago
-    var dateOffset = (24*60*60*1000) / 2; //12 hr ago
-    var date = new Date();
-    date.setTime(date.getTime() - dateOffset);
+    var date = daysAgo(0.5, new Date());
 
-    let results_betwee2 = await alice.cells[0].callZome({
-      zome_name: "test_zome", 
-      fn_name: "get_indexes_for_time_span",
-      payload: {index: "test_index", from: date.toISOString(), until: new Date().toISOString(), limit: 10}
-    })
-    console.log("Got results between", results_betwee2);
+    let results_betwee2 = await callTestZome(alice, "get_indexes_for_time_span", {index: "test_index", from: date.toISOString(), until: new Date().toISOString(), limit: 10})
     //@ts-ignore
     t.deepEqual(results_betwee2.length, 1);
 
@@ -266,44 +214,889 @@ test("test delete", async (t) => {
     const [alice] = await scenario.addPlayersWithHapps([dnas]);
   
     //Index entry
-    await alice.cells[0].callZome({
-      zome_name: "test_zome",
-      fn_name:  "index_entry",
-      payload: {title: "A test index", created: new Date().toISOString()}
-    })
+    await indexEntries(alice, [["A test index", new Date()]])
   
     //Create another index for one day ago
-    var dateOffset = (24*60*60*1000); //1 day ago
-    var date = new Date();
-    date.setTime(date.getTime() - dateOffset);
+    var date = daysAgo(1, new Date());
   
-    let rb = await alice.cells[0].callZome({
-      zome_name: "test_zome",
-      fn_name:  "get_indexes_for_time_span",
-      payload: {index: "test_index", from: date.toISOString(), until: new Date().toISOString(), limit: 10}
-    })
-    console.log("Got results between", rb);
+    let rb = await callTestZome(alice, "get_indexes_for_time_span", {index: "test_index", from: date.toISOString(), until: new Date().toISOString(), limit: 10})
     //@ts-ignore
     t.deepEqual(rb.length, 1);
     //@ts-ignore
     console.log("deleting entry at", rb[0].links[0].target);
     
-    await alice.cells[0].callZome({
-      zome_name: "test_zome",
-      fn_name:  "remove_index",
-      //@ts-ignore
-      payload: rb[0].links[0].target
-    })
+    //@ts-ignore
+    
+    await callTestZome(alice, "remove_index", rb[0].links[0].target)
   
-    let rb_pd = await alice.cells[0].callZome({
-      zome_name: "test_zome",
-      fn_name:  "get_links_for_time_span",
-      payload: {index: "test_index", from: date.toISOString(), until: new Date().toISOString(), limit: 10}
-    })
-    console.log("Got results between post delete", rb_pd);
+    let rb_pd = await callTestZome(alice, "get_links_for_time_span", {index: "test_index", from: date.toISOString(), until: new Date().toISOString(), limit: 10})
     //@ts-ignore
     t.deepEqual(rb_pd.length, 0);
 
     await scenario.cleanUp()
   })
 })
+
+test("test get indexed items", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entry
+    await indexTestEntries(alice)
+
+    let results_between = await callTestZome(alice, "get_indexed_items_for_time_span", {index: "test_index", from: now.toISOString(), until: yesterday.toISOString(), limit: 10})
+    //@ts-ignore
+    t.equal(results_between.length, 2)
+    //@ts-ignore
+    t.equal(results_between[0].entry.title, "A test index")
+    //@ts-ignore
+    t.deepEqual(results_between[0].action.hashed.content.author, alice.agentPubKey)
+    //@ts-ignore
+    t.deepEqual(results_between[0].link.target, results_between[0].action.hashed.content.entry_hash)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test lenient search skips undecodable targets", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entry
+    await indexEntries(alice, [["A test index", now]])
+    //Index the agent's key, which cannot be decoded as a TestEntry
+    await callTestZome(alice, "index_agent", {created: yesterday.toISOString()})
+
+    let [results, skipped] = await callTestZome(alice, "get_indexed_items_for_time_span_lenient", {index: "test_index", from: now.toISOString(), until: yesterday.toISOString(), limit: 10})
+    //@ts-ignore
+    t.equal(results.length, 1)
+    //@ts-ignore
+    t.equal(results[0].entry.title, "A test index")
+    //@ts-ignore
+    t.equal(skipped.length, 1)
+    //@ts-ignore
+    t.deepEqual(skipped[0][0].target, alice.agentPubKey)
+    //@ts-ignore
+    t.ok(skipped[0][1].includes("Expected element to contain app entry data"))
+
+    //Index an entry which was never created, so its record cannot be found
+    await callTestZome(alice, "index_missing_entry", {title: "A missing index", created: yesterday.toISOString()})
+    let [_results, skipped_missing] = await callTestZome(alice, "get_indexed_items_for_time_span_lenient", {index: "test_index", from: now.toISOString(), until: yesterday.toISOString(), limit: 10})
+    //@ts-ignore
+    t.equal(skipped_missing.length, 2)
+    //@ts-ignore
+    t.ok(skipped_missing.some(([_link, error]) => error.includes("Could not find link target")))
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test tagged links are filtered by tag", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entries with different tags
+    await callTestZome(alice, "index_tagged_entry", {entry: {title: "A test index", created: now.toISOString()}, kind: "note"})
+    await callTestZome(alice, "index_tagged_entry", {entry: {title: "A test index2", created: yesterday.toISOString()}, kind: "image"})
+    await callTestZome(alice, "index_tagged_entry", {entry: {title: "A test index3", created: yesterday.toISOString()}, kind: "note"})
+
+    let links = await callTestZome(alice, "get_tagged_links_for_time_span", {index: "test_index", from: yesterday.toISOString(), until: now.toISOString(), kind: null})
+    //@ts-ignore
+    t.equal(links.length, 3)
+
+    links = await callTestZome(alice, "get_tagged_links_for_time_span", {index: "test_index", from: yesterday.toISOString(), until: now.toISOString(), kind: "note"})
+    //@ts-ignore
+    t.equal(links.length, 2)
+    //@ts-ignore
+    t.ok(links.every(([_link, tag]) => tag.kind == "note"))
+
+    links = await callTestZome(alice, "get_tagged_links_for_time_span", {index: "test_index", from: yesterday.toISOString(), until: now.toISOString(), kind: "image"})
+    //@ts-ignore
+    t.equal(links.length, 1)
+    //@ts-ignore
+    t.equal(links[0][1].kind, "image")
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test query budget", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entry
+    await indexTestEntries(alice)
+
+    //Budget only allows one chunk to be read per call
+    let first_page = await callTestZome(alice, "get_indexed_items_with_budget", {index: "test_index", from: now.toISOString(), until: yesterday.toISOString(), limit: 10})
+    //@ts-ignore
+    t.equal(first_page.results.length, 1)
+    //@ts-ignore
+    t.equal(first_page.results[0].entry.title, "A test index")
+    //@ts-ignore
+    t.ok(first_page.continuation)
+
+    //@ts-ignore
+
+    let second_page = await callTestZome(alice, "get_indexed_items_with_budget", {index: "test_index", from: first_page.continuation, until: yesterday.toISOString(), limit: 10})
+    //@ts-ignore
+    t.equal(second_page.results.length, 1)
+    //@ts-ignore
+    t.equal(second_page.results[0].entry.title, "A test index2")
+    //@ts-ignore
+    t.equal(second_page.continuation, null)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test truncated links page keeps its continuation", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entries into two chunks, each holding two links
+    for (const created of [now, yesterday]) {
+      for (const title of ["A test index", "A test index2"]) {
+        await callTestZome(alice, "index_entry", {title: title, created: created.toISOString()})
+      }
+    }
+
+    //Links are ordered by creation so the unread chunk could hold links ranking ahead of the ones returned. The page is
+    //truncated to the limit but still continues at the unread chunk
+    let page = await callTestZome(alice, "get_links_with_budget", {index: "test_index", from: now.toISOString(), until: yesterday.toISOString(), limit: 1})
+    //@ts-ignore
+    t.equal(page.results.length, 1)
+    //@ts-ignore
+    t.ok(page.continuation)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test query budget exhausted during tree walk", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entry
+    await indexEntries(alice, [["A test index", now]])
+
+    //Walking from the day level down to the chunks takes more than two get_links calls. Nothing was read which the search could
+    //continue after so it fails
+    let failed = false;
+    try {
+      await callTestZome(alice, "get_indexed_items_with_host_call_budget", {index: "test_index", from: now.toISOString(), until: yesterday.toISOString(), max_host_calls: 2})
+    } catch (error) {
+      failed = true;
+    }
+    t.ok(failed)
+
+    //Enough budget to walk the tree and read the chunks returns the entry
+    let page = await callTestZome(alice, "get_indexed_items_with_host_call_budget", {index: "test_index", from: now.toISOString(), until: yesterday.toISOString(), max_host_calls: 100})
+    //@ts-ignore
+    t.equal(page.results.length, 1)
+    //@ts-ignore
+    t.equal(page.continuation, null)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test dfs search resumes after running out of budget", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    var twoDaysAgo = daysAgo(2);
+    var threeDaysAgo = daysAgo(3);
+
+    //Index entries on different days
+    await indexEntries(alice, [["A test index", now], ["A test index2", twoDaysAgo]])
+
+    //Running out of budget during the first descent of the tree fails rather than returning a page continuing from the start
+    let failed = false;
+    try {
+      await callTestZome(alice, "get_indexed_items_dfs_with_host_call_budget", {index: "test_index", from: now.toISOString(), until: threeDaysAgo.toISOString(), max_host_calls: 2})
+    } catch (error) {
+      failed = true;
+    }
+    t.ok(failed)
+
+    //Descending to the newest chunk takes three get_links calls, one for each of the day, hour and minute levels, and reading it
+    //one more get_links call for the chunk and two to load its entries. The older day needs three more get_links calls before
+    //its chunk could be read with another two, which the budget does not allow
+    let first_page = await callTestZome(alice, "get_indexed_items_dfs_with_host_call_budget", {index: "test_index", from: now.toISOString(), until: threeDaysAgo.toISOString(), max_host_calls: 10})
+    //@ts-ignore
+    t.equal(first_page.results.length, 1)
+    //@ts-ignore
+    t.equal(first_page.results[0].entry.title, "A test index")
+    //The search continues from before the chunk it read
+    //@ts-ignore
+    t.ok(new Date(first_page.continuation).getTime() < now.getTime())
+
+    //Resuming with the same budget makes progress and finds the older entry. The newest chunk is outside of the resumed range so is
+    //not read again
+    //@ts-ignore
+    let second_page = await callTestZome(alice, "get_indexed_items_dfs_with_host_call_budget", {index: "test_index", from: first_page.continuation, until: threeDaysAgo.toISOString(), max_host_calls: 10})
+    //@ts-ignore
+    t.equal(second_page.results.length, 1)
+    //@ts-ignore
+    t.equal(second_page.results[0].entry.title, "A test index2")
+    //@ts-ignore
+    t.equal(second_page.continuation, null)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test explain search", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entry
+    await indexTestEntries(alice)
+
+    let [page, plan] = await callTestZome(alice, "explain_indexed_items_for_time_span", {index: "test_index", from: now.toISOString(), until: yesterday.toISOString(), limit: 10})
+    //@ts-ignore
+    t.equal(page.results.length, 2)
+    //@ts-ignore
+    t.equal(plan.chunks_read.length, 2)
+    //@ts-ignore
+    t.ok(plan.get_links_calls > 0)
+    //@ts-ignore
+    t.ok(plan.get_calls > 0)
+    //@ts-ignore
+    t.ok(plan.levels.length > 0)
+    //@ts-ignore
+    t.ok(plan.search_graph.startsWith("digraph"))
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test bfs search batches host calls", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    var twoDaysAgo = daysAgo(2);
+
+    //Index entries into three chunks
+    await indexEntries(alice, [["A test index", now], ["A test index2", yesterday], ["A test index3", twoDaysAgo]])
+
+    //Without a limit the links in every chunk are fetched with one get_links call and their entries with one get call
+    let [page, plan] = await callTestZome(alice, "explain_bfs_indexed_items_for_time_span", {index: "test_index", from: now.toISOString(), until: twoDaysAgo.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(page.results.length, 3)
+    //@ts-ignore
+    t.equal(plan.chunks_read.length, 3)
+    //@ts-ignore
+    t.equal(plan.get_calls, 1)
+    //One get_links call per level of the tree walked plus one for the links in the chunks
+    //@ts-ignore
+    t.equal(plan.get_links_calls, plan.levels.length + 2)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test bfs search stops at limit", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    var twoDaysAgo = daysAgo(2);
+
+    //Index entries into three chunks
+    await indexEntries(alice, [["A test index", now], ["A test index2", yesterday], ["A test index3", twoDaysAgo]])
+
+    //Chunks are read newest first and the search stops once the limit is reached
+    let [page, plan] = await callTestZome(alice, "explain_bfs_indexed_items_for_time_span", {index: "test_index", from: now.toISOString(), until: twoDaysAgo.toISOString(), limit: 1})
+    //@ts-ignore
+    t.equal(page.results.length, 1)
+    //@ts-ignore
+    t.equal(page.results[0].entry.title, "A test index")
+    //@ts-ignore
+    t.equal(plan.chunks_read.length, 1)
+    //@ts-ignore
+    t.equal(page.continuation, null)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test auto search switches after expanding a branch", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    var twoDaysAgo = daysAgo(2);
+    var threeDaysAgo = daysAgo(3);
+    var fourDaysAgo = daysAgo(4);
+
+    //Index entries into four days
+    await indexEntries(alice, [["A test index", now], ["A test index2", yesterday], ["A test index3", twoDaysAgo], ["A test index4", threeDaysAgo]])
+
+    //Remove the two newest entries so their chunks are empty
+    let indexes = await callTestZome(alice, "get_indexes_for_time_span", {index: "test_index", from: yesterday.toISOString(), until: now.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(indexes.length, 2);
+    //@ts-ignore
+    for (const index of indexes) {
+      await callTestZome(alice, "remove_index", index.links[0].target)
+    }
+
+    //The first empty chunk is not enough to switch as three days are left to search. The dfs then expands the branch of the
+    //next day and finds its chunk empty too, after which the remaining days are fetched level by level
+    let [page, plan] = await callTestZome(alice, "explain_auto_indexed_items_for_time_span", {index: "test_index", from: now.toISOString(), until: fourDaysAgo.toISOString(), limit: 1})
+    //@ts-ignore
+    t.equal(page.results.length, 1)
+    //@ts-ignore
+    t.equal(page.results[0].entry.title, "A test index3")
+    //Every chunk is read at most once and the level wise expansion stops at the limit, leaving the oldest chunk unread
+    //@ts-ignore
+    t.equal(plan.chunks_read.length, 3)
+    //@ts-ignore
+    t.equal(new Set(plan.chunks_read.map((chunk) => chunk.from)).size, 3)
+    //@ts-ignore
+    t.ok(plan.chunks_read.every((chunk) => chunk.from.secs > Math.floor(threeDaysAgo.getTime() / 1000)))
+    //@ts-ignore
+    t.ok(plan.search_graph)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test time tree snapshot", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entry
+    await indexTestEntries(alice)
+
+    //Second search reuses the paths found by the first so only needs to get the links in each chunk
+    let plans = await callTestZome(alice, "explain_with_snapshot", {index: "test_index", from: yesterday.toISOString(), until: now.toISOString(), limit: null})
+    //@ts-ignore
+    t.ok(plans[0].get_links_calls > 1)
+    //@ts-ignore
+    t.equal(plans[1].get_links_calls, 1)
+    //@ts-ignore
+    t.deepEqual(plans[1].chunks_read, plans[0].chunks_read)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test open ended time range", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Nothing indexed yet so the range has nothing to resolve to
+    let items = await callTestZome(alice, "get_indexed_items_for_open_range", {index: "test_index", limit: null, link_tag: null})
+    //@ts-ignore
+    t.equal(items.length, 0)
+
+    //Index entry
+    await indexTestEntries(alice)
+
+    //Unbounded range reaches from the oldest to the newest indexed chunk, oldest first
+    items = await callTestZome(alice, "get_indexed_items_for_open_range", {index: "test_index", limit: null, link_tag: null})
+    //@ts-ignore
+    t.equal(items.length, 2)
+    //@ts-ignore
+    t.equal(items[0].entry.title, "A test index2")
+    //@ts-ignore
+    t.equal(items[1].entry.title, "A test index")
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test index bounds", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    let bounds = await callTestZome(alice, "get_index_bounds", "test_index")
+    t.equal(bounds, null)
+
+    //Index entry
+    await indexTestEntries(alice)
+
+    bounds = await callTestZome(alice, "get_index_bounds", "test_index")
+    //@ts-ignore
+    t.ok(bounds[0].from.secs <= Math.floor(yesterday.getTime() / 1000))
+    //@ts-ignore
+    t.ok(bounds[1].until.secs > Math.floor(now.getTime() / 1000))
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test latest index skips deleted links", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entry
+    await indexTestEntries(alice)
+
+    let latest = await callTestZome(alice, "get_latest_index_with_links", "test_index")
+    //@ts-ignore
+    t.ok(latest.until.secs > Math.floor(now.getTime() / 1000))
+
+    let rb = await callTestZome(alice, "get_indexes_for_time_span", {index: "test_index", from: yesterday.toISOString(), until: now.toISOString(), limit: 10})
+    //@ts-ignore
+    await callTestZome(alice, "remove_index", rb[0].links[0].target)
+
+    //Newest chunk has no links left so the next newest branch is used
+    latest = await callTestZome(alice, "get_latest_index_with_links", "test_index")
+    //@ts-ignore
+    t.ok(latest.until.secs <= Math.floor(yesterday.getTime() / 1000) + 1)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test latest index skips future branches", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entry
+    await indexEntries(alice, [["A test index", now]])
+    //Branch for the year 2099 as written by an agent whose clock ran ahead
+    await callTestZome(alice, "create_future_path", "test_index")
+
+    //The latest chunk is the one the entry was indexed into
+    const chunk = chunkMs(now, await callTestZome(alice, "get_chunk_interval", null))
+    let latest = await callTestZome(alice, "get_latest_index", {index: "test_index"})
+    t.deepEqual({from: durationMs(latest.from), until: durationMs(latest.until)}, chunk)
+
+    latest = await callTestZome(alice, "get_latest_index_with_links", "test_index")
+    t.deepEqual({from: durationMs(latest.from), until: durationMs(latest.until)}, chunk)
+
+    let bounds = await callTestZome(alice, "get_index_bounds", "test_index")
+    t.deepEqual({from: durationMs(bounds[1].from), until: durationMs(bounds[1].until)}, chunk)
+
+    //Allowing a skew of a hundred years reaches the future branch
+    latest = await callTestZome(alice, "get_latest_index", {index: "test_index", max_future_skew_secs: 100 * 365 * 24 * 60 * 60})
+    t.ok(durationMs(latest.from) >= chunk.until)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test prune before", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    var cutoff = new Date(now.getTime() - (60*60*1000)); //1 hour ago
+
+    //Index entry
+    await indexTestEntries(alice)
+
+    let report = await callTestZome(alice, "prune_before", {index: "test_index", cutoff: cutoff.toISOString(), max_chunks: 10})
+    //@ts-ignore
+    t.equal(report.chunks.length, 1)
+    //@ts-ignore
+    t.equal(report.index_links, 1)
+    //@ts-ignore
+    t.equal(report.time_path_links, 1)
+    //@ts-ignore
+    t.ok(report.complete)
+
+    //Only the entry after the cutoff is left
+    let links = await callTestZome(alice, "get_links_for_time_span", {index: "test_index", from: now.toISOString(), until: yesterday.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(links.length, 1)
+
+    //Nothing is left to prune
+    report = await callTestZome(alice, "prune_before", {index: "test_index", cutoff: cutoff.toISOString(), max_chunks: 10})
+    //@ts-ignore
+    t.equal(report.chunks.length, 0)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test prune before in batches", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    var twoDaysAgo = daysAgo(2);
+    var cutoff = new Date(now.getTime() - (60*60*1000)); //1 hour ago
+
+    //Index entries into two chunks before the cutoff, on different days, and one after it
+    await indexEntries(alice, [["A test index", now], ["A test index2", yesterday], ["A test index3", twoDaysAgo]])
+
+    //Only one chunk is removed per call, oldest first, so another call is needed
+    let report = await callTestZome(alice, "prune_before", {index: "test_index", cutoff: cutoff.toISOString(), max_chunks: 1})
+    //@ts-ignore
+    t.equal(report.chunks.length, 1)
+    //@ts-ignore
+    t.ok(report.chunks[0].from.secs <= Math.floor(twoDaysAgo.getTime() / 1000))
+    //@ts-ignore
+    t.notOk(report.complete)
+    //@ts-ignore
+    t.ok(report.continuation)
+    //The second, minute, hour and day paths above the chunk are left empty and unlinked. The month path still leads to the
+    //other days so is kept
+    //@ts-ignore
+    t.equal(report.paths.length, 4)
+
+    let links = await callTestZome(alice, "get_links_for_time_span", {index: "test_index", from: now.toISOString(), until: twoDaysAgo.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(links.length, 2)
+
+    //Resuming removes the remaining chunk before the cutoff
+    //@ts-ignore
+    report = await callTestZome(alice, "prune_before", {index: "test_index", cutoff: cutoff.toISOString(), max_chunks: 1, resume_from: report.continuation})
+    //@ts-ignore
+    t.equal(report.chunks.length, 1)
+    //@ts-ignore
+    t.ok(report.chunks[0].from.secs <= Math.floor(yesterday.getTime() / 1000))
+    //@ts-ignore
+    t.ok(report.complete)
+    //@ts-ignore
+    t.equal(report.paths.length, 4)
+
+    //The day paths of pruned chunks are no longer part of the tree
+    let bounds = await callTestZome(alice, "get_index_bounds", "test_index")
+    //@ts-ignore
+    t.ok(bounds[0].from.secs > Math.floor(cutoff.getTime() / 1000))
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test prune before within a budget", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    var twoDaysAgo = daysAgo(2);
+    var cutoff = new Date(now.getTime() - (60*60*1000)); //1 hour ago
+
+    //Index entries into two chunks before the cutoff, on different days, and one after it
+    await indexEntries(alice, [["A test index", now], ["A test index2", yesterday], ["A test index3", twoDaysAgo]])
+
+    //A budget which does not allow the tree to be walked fails without removing anything
+    let failed = false;
+    try {
+      await callTestZome(alice, "prune_before", {index: "test_index", cutoff: cutoff.toISOString(), max_chunks: 1, max_host_calls: 1})
+    } catch (error) {
+      failed = true;
+    }
+    t.ok(failed)
+
+    //Each call walks the tree within the budget and the second resumes from where the first stopped
+    let report = await callTestZome(alice, "prune_before", {index: "test_index", cutoff: cutoff.toISOString(), max_chunks: 1, max_host_calls: 60})
+    //@ts-ignore
+    t.equal(report.chunks.length, 1)
+    //@ts-ignore
+    t.notOk(report.complete)
+    //@ts-ignore
+    report = await callTestZome(alice, "prune_before", {index: "test_index", cutoff: cutoff.toISOString(), max_chunks: 1, resume_from: report.continuation, max_host_calls: 60})
+    //@ts-ignore
+    t.equal(report.chunks.length, 1)
+    //@ts-ignore
+    t.ok(report.complete)
+    //@ts-ignore
+    t.equal(report.continuation, null)
+
+    //The index links of the pruned entries are gone
+    let links = await callTestZome(alice, "get_links_for_time_span", {index: "test_index", from: now.toISOString(), until: twoDaysAgo.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(links.length, 1)
+
+    //The paths down to the pruned chunks are unlinked from their parents, so walking the tree no longer finds the chunks
+    let chunks = await callTestZome(alice, "get_indexes_for_time_span", {index: "test_index", from: twoDaysAgo.toISOString(), until: cutoff.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(chunks.length, 0)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test prune before keeps other agents links", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice, bob] = await scenario.addPlayersWithHapps([dnas, dnas]);
+    await scenario.shareAllAgents();
+
+    var cutoff = new Date(now.getTime() - (60*60*1000)); //1 hour ago
+
+    //Alice and bob index into the same chunk before the cutoff
+    await callTestZome(bob, "index_entry", {title: "A test index", created: yesterday.toISOString()})
+    await callTestZome(alice, "index_entry", {title: "A test index2", created: yesterday.toISOString()})
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+
+    //A call which could never remove anything is rejected
+    let failed = false;
+    try {
+      await callTestZome(alice, "prune_before", {index: "test_index", cutoff: cutoff.toISOString(), max_chunks: 0})
+    } catch (error) {
+      failed = true;
+    }
+    t.ok(failed)
+
+    //Only alice's links are removed and the chunk stays linked as bob's entry is still in it
+    let report = await callTestZome(alice, "prune_before", {index: "test_index", cutoff: cutoff.toISOString(), max_chunks: 10})
+    //@ts-ignore
+    t.equal(report.index_links, 1)
+    //@ts-ignore
+    t.equal(report.time_path_links, 1)
+    //@ts-ignore
+    t.equal(report.paths.length, 0)
+
+    let chunks = await callTestZome(alice, "get_indexes_for_time_span", {index: "test_index", from: yesterday.toISOString(), until: cutoff.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(chunks.length, 1)
+    //@ts-ignore
+    t.equal(chunks[0].links.length, 1)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test remove author links", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entry
+    await indexTestEntries(alice)
+
+    let removals = await callTestZome(alice, "remove_author_links", {index: "test_index", from: yesterday.toISOString(), until: now.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(removals.length, 2)
+    //@ts-ignore
+    t.equal(removals[0].index_links, 1)
+    //@ts-ignore
+    t.equal(removals[0].time_path_links, 1)
+
+    let links = await callTestZome(alice, "get_links_for_time_span", {index: "test_index", from: now.toISOString(), until: yesterday.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(links.length, 0)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test remove author links for entry indexed twice", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index the same entry twice so that it has two index links and two time_path links into the same chunk
+    await indexEntries(alice, [["A test index", now], ["A test index", now]])
+
+    let removals = await callTestZome(alice, "remove_author_links", {index: "test_index", from: yesterday.toISOString(), until: now.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(removals.length, 1)
+    //@ts-ignore
+    t.equal(removals[0].index_links, 2)
+    //@ts-ignore
+    t.equal(removals[0].time_path_links, 2)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test index changes include deletes", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([dnas]);
+
+    //Index entry
+    await indexTestEntries(alice)
+
+    let rb = await callTestZome(alice, "get_indexes_for_time_span", {index: "test_index", from: yesterday.toISOString(), until: now.toISOString(), limit: 10})
+    //@ts-ignore
+    await callTestZome(alice, "remove_index", rb[0].links[0].target)
+
+    let changes = await callTestZome(alice, "get_index_changes_for_time_span", {index: "test_index", from: yesterday.toISOString(), until: now.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(changes.length, 2)
+    //@ts-ignore
+    t.equal(changes[0].created.length, 1)
+    //@ts-ignore
+    t.equal(changes[0].deleted.length, 0)
+    //@ts-ignore
+    t.equal(changes[1].created.length, 1)
+    //@ts-ignore
+    t.equal(changes[1].deleted.length, 1)
+
+    //Chunks follow the order of the range, a range from now until yesterday returns the newest chunk first
+    let newestFirst = await callTestZome(alice, "get_index_changes_for_time_span", {index: "test_index", from: now.toISOString(), until: yesterday.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(newestFirst.length, 2)
+    //@ts-ignore
+    t.equal(newestFirst[0].deleted.length, 1)
+    //@ts-ignore
+    t.equal(newestFirst[1].deleted.length, 0)
+
+    //The ingest log is off by default so nothing is recorded in it
+    let logged = await callTestZome(alice, "get_changes_since", {index: "test_index", since: new Date(0).toISOString()})
+    //@ts-ignore
+    t.equal(logged.length, 0)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test changes since", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([ingestLogDnas]);
+
+    var checkpoint = new Date();
+
+    //Backdated entry is still found as a change made after the checkpoint
+    await indexEntries(alice, [["A test index2", yesterday]])
+
+    let changes = await callTestZome(alice, "get_changes_since", {index: "test_index", since: checkpoint.toISOString()})
+    //@ts-ignore
+    t.equal(changes.length, 1)
+    //@ts-ignore
+    t.equal(changes[0].kind, "Added")
+
+    //@ts-ignore
+
+    await callTestZome(alice, "remove_index_and_log", changes[0].target)
+
+    changes = await callTestZome(alice, "get_changes_since", {index: "test_index", since: checkpoint.toISOString()})
+    //@ts-ignore
+    t.equal(changes.length, 2)
+    //@ts-ignore
+    t.equal(changes[1].kind, "Removed")
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test changes since include entries backdated into an old chunk", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([ingestLogDnas]);
+
+    //Chunk for yesterday exists before the checkpoint
+    await indexEntries(alice, [["A test index", yesterday]])
+    let changes = await callTestZome(alice, "get_changes_since", {index: "test_index", since: new Date(0).toISOString()})
+    //@ts-ignore
+    t.equal(changes.length, 1)
+    //@ts-ignore
+    let first = changes[0].target
+    var checkpoint = new Date();
+
+    //Second entry is backdated into the same chunk after the checkpoint
+    await indexEntries(alice, [["A test index2", yesterday]])
+    let rb = await callTestZome(alice, "get_indexes_for_time_span", {index: "test_index", from: yesterday.toISOString(), until: now.toISOString(), limit: 10})
+    //@ts-ignore
+    t.equal(rb.length, 1)
+    //@ts-ignore
+    t.equal(rb[0].links.length, 2)
+
+    changes = await callTestZome(alice, "get_changes_since", {index: "test_index", since: checkpoint.toISOString()})
+    //@ts-ignore
+    t.equal(changes.length, 1)
+    //@ts-ignore
+    t.equal(changes[0].kind, "Added")
+    //@ts-ignore
+    t.notDeepEqual(changes[0].target, first)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test pruned entries are recorded in changes since", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice] = await scenario.addPlayersWithHapps([ingestLogDnas]);
+
+    var cutoff = new Date(now.getTime() - (60*60*1000)); //1 hour ago
+    var checkpoint = new Date();
+
+    await indexEntries(alice, [["A test index2", yesterday]])
+    await callTestZome(alice, "prune_before", {index: "test_index", cutoff: cutoff.toISOString(), max_chunks: 10})
+
+    let changes = await callTestZome(alice, "get_changes_since", {index: "test_index", since: checkpoint.toISOString()})
+    //@ts-ignore
+    t.equal(changes.length, 2)
+    //@ts-ignore
+    t.equal(changes[1].kind, "Removed")
+    //@ts-ignore
+    t.deepEqual(changes[1].target, changes[0].target)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test index signals", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice, bob] = await scenario.addPlayersWithHapps([dnas, dnas]);
+    await scenario.shareAllAgents();
+
+    let received: any[] = [];
+    bob.conductor.appWs().on("signal", (signal) => received.push(signal.data.payload));
+
+    //Bob's signal has no other authors in the chunk to be sent to
+    await callTestZome(bob, "index_entry_with_signal", {title: "A test index", created: now.toISOString()})
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+
+    //Alice indexes into the same chunk so bob is signalled as a chunk author
+    await callTestZome(alice, "index_entry_with_signal", {title: "A test index2", created: now.toISOString()})
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+
+    console.log("Got signals", received);
+    t.equal(received.length, 1)
+    t.equal(received[0].index, "test_index")
+
+    //Once bob's index link is deleted he is no longer an author of the chunk; his link is the older of the two in the chunk
+    let indexes = await callTestZome(bob, "get_indexes_for_time_span", {index: "test_index", from: now.toISOString(), until: now.toISOString(), limit: null})
+    //@ts-ignore
+    t.equal(indexes[0].links.length, 2)
+    //@ts-ignore
+    await callTestZome(bob, "remove_index", indexes[0].links[1].target)
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+
+    await callTestZome(alice, "index_entry_with_signal", {title: "A test index3", created: now.toISOString()})
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+    t.equal(received.length, 1)
+
+    await scenario.cleanUp()
+  })
+})
+
+test("test subscribers", async (t) => {
+  await runScenario(async (scenario: Scenario) => {
+    const [alice, bob] = await scenario.addPlayersWithHapps([dnas, dnas]);
+    await scenario.shareAllAgents();
+
+    await callTestZome(alice, "subscribe", "test_index")
+    //Subscribing again in the same chunk should not add a second registration
+    await callTestZome(alice, "subscribe", "test_index")
+    //Bob registers in a later chunk than alice
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+    await callTestZome(bob, "subscribe", "test_index")
+    await new Promise((resolve) => setTimeout(resolve, 2000));
+
+    let subscribers = await callTestZome(alice, "get_subscribers", {index: "test_index", ttl_secs: 60, max_chunks: null, resume_from: null})
+    //@ts-ignore
+    t.equal(subscribers.results.length, 2)
+    //@ts-ignore
+    t.equal(subscribers.continuation, null)
+
+    //Registrations older than the ttl are left out
+    let recent = await callTestZome(alice, "get_subscribers", {index: "test_index", ttl_secs: 0, max_chunks: null, resume_from: null})
+    //@ts-ignore
+    t.equal(recent.results.length, 0)
+
+    //A budget of one chunk reads the most recent registrations first and continues from there
+    let page = await callTestZome(alice, "get_subscribers", {index: "test_index", ttl_secs: 60, max_chunks: 1, resume_from: null})
+    //@ts-ignore
+    t.deepEqual(page.results, [bob.agentPubKey])
+    //@ts-ignore
+    t.ok(page.continuation)
+    //@ts-ignore
+    let rest = await callTestZome(alice, "get_subscribers", {index: "test_index", ttl_secs: 60, max_chunks: 1, resume_from: page.continuation})
+    //@ts-ignore
+    t.deepEqual(rest.results, [alice.agentPubKey])
+
+    let removed = await callTestZome(alice, "unsubscribe", {index: "test_index", ttl_secs: 60})
+    t.equal(removed, 1)
+
+    subscribers = await callTestZome(alice, "get_subscribers", {index: "test_index", ttl_secs: 60, max_chunks: null, resume_from: null})
+    //@ts-ignore
+    t.equal(subscribers.results.length, 1)
+    //@ts-ignore
+    t.deepEqual(subscribers.results[0], bob.agentPubKey)
+
+    await scenario.cleanUp()
+  })
+})
@@ -3,11 +3,20 @@ use hdk::prelude::*;
 use test_zome_integrity::{TestEntry, EntryTypes, LinkTypes};
 
 use hc_time_index::*;
+use hdk::hash_path::path::Component;
 
 mod utils;
 
 #[hdk_extern]
 pub fn init(_: ()) -> ExternResult<InitCallbackResult> {
+    //Allow other agents to send index signals
+    let mut functions: GrantedFunctions = BTreeSet::new();
+    functions.insert((zome_info()?.name, "recv_remote_signal".into()));
+    create_cap_grant(CapGrantEntry {
+        tag: "".into(),
+        access: ().into(),
+        functions,
+    })?;
     Ok(InitCallbackResult::Pass)
 }
 
@@ -15,10 +24,81 @@ pub fn init(_: ()) -> ExternResult<InitCallbackResult> {
 pub fn index_entry(entry: TestEntry) -> ExternResult<()> {
     create_entry(&EntryTypes::TestEntry(entry.clone()))?;
     hc_time_index::index_entry(String::from("test_index"), entry, LinkTag::new("test"), LinkTypes::Index, LinkTypes::Path)
-        .map_err(|error| utils::err(&format!("{}", error)))?;
+        .map_err(utils::index_err)?;
     Ok(())
 }
 
+/// Index an entry without creating it, so that the index link points at a target which cannot be found
+#[hdk_extern]
+pub fn index_missing_entry(entry: TestEntry) -> ExternResult<()> {
+    hc_time_index::index_entry(String::from("test_index"), entry, LinkTag::new("test"), LinkTypes::Index, LinkTypes::Path)
+        .map_err(utils::index_err)
+}
+
+#[hdk_extern]
+pub fn index_entry_with_signal(entry: TestEntry) -> ExternResult<()> {
+    create_entry(&EntryTypes::TestEntry(entry.clone()))?;
+    hc_time_index::index_entry_with_signal(
+        String::from("test_index"),
+        entry,
+        LinkTag::new("test"),
+        SignalRecipients::ChunkAuthors,
+        LinkTypes::Index,
+        LinkTypes::Path,
+    )
+    .map_err(utils::index_err)?;
+    Ok(())
+}
+
+#[hdk_extern]
+pub fn recv_remote_signal(signal: SerializedBytes) -> ExternResult<()> {
+    let index_signal = hc_time_index::decode_index_signal(signal).map_err(utils::index_err)?;
+    emit_signal(index_signal)?;
+    Ok(())
+}
+
+#[hdk_extern]
+pub fn subscribe(index: String) -> ExternResult<()> {
+    hc_time_index::subscribe(index, LinkTypes::Index, LinkTypes::Path)
+        .map_err(utils::index_err)
+}
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+pub struct UnsubscribeInput {
+    pub index: String,
+    pub ttl_secs: u64,
+}
+
+#[hdk_extern]
+pub fn unsubscribe(input: UnsubscribeInput) -> ExternResult<usize> {
+    hc_time_index::unsubscribe(input.index, std::time::Duration::from_secs(input.ttl_secs), LinkTypes::Index, LinkTypes::Path)
+        .map_err(utils::index_err)
+}
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+pub struct GetSubscribersInput {
+    pub index: String,
+    pub ttl_secs: u64,
+    pub max_chunks: Option<usize>,
+    pub resume_from: Option<DateTime<Utc>>,
+}
+
+#[hdk_extern]
+pub fn get_subscribers(input: GetSubscribersInput) -> ExternResult<Page<AgentPubKey>> {
+    hc_time_index::get_subscribers(
+        input.index,
+        std::time::Duration::from_secs(input.ttl_secs),
+        input.resume_from,
+        QueryOptions::default().with_budget(QueryBudget {
+            max_host_calls: None,
+            max_chunks: input.max_chunks,
+        }),
+        LinkTypes::Index,
+        LinkTypes::Path,
+    )
+    .map_err(utils::index_err)
+}
+
 #[derive(Serialize, Deserialize, SerializedBytes, Debug)]
 pub struct GetAddressesSinceInput {
     pub index: String,
@@ -34,42 +114,456 @@ pub fn get_indexes_for_time_span(
 ) -> ExternResult<Vec<hc_time_index::EntryChunkIndex>> {
     hc_time_index::get_indexes_for_time_span(
         input.index,
-        input.from,
-        input.until,
-        input.link_tag,
+        IndexQuery::new(TimeRange::new(input.from, input.until)).with_tag(input.link_tag.into()),
+        QueryOptions::default(),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map(|page| page.results)
+    .map_err(utils::index_err)
+}
+
+#[hdk_extern]
+pub fn get_index_changes_for_time_span(
+    input: GetAddressesSinceInput,
+) -> ExternResult<Vec<hc_time_index::EntryChunkChanges>> {
+    hc_time_index::get_index_changes_for_time_span(
+        input.index,
+        IndexQuery::new(TimeRange::new(input.from, input.until)).with_tag(input.link_tag.into()),
+        QueryOptions::default(),
         LinkTypes::Index,
         LinkTypes::Path
     )
-    .map_err(|error| utils::err(&format!("{}", error)))
+    .map(|page| page.results)
+    .map_err(utils::index_err)
 }
 
 #[hdk_extern]
 pub fn get_links_for_time_span(input: GetAddressesSinceInput) -> ExternResult<Vec<Link>> {
     Ok(hc_time_index::get_links_for_time_span(
         input.index,
-        input.from,
-        input.until,
-        input.link_tag,
-        Some(10),
+        IndexQuery {
+            link_tag: input.link_tag.into(),
+            limit: Some(10),
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::default(),
         LinkTypes::Index,
         LinkTypes::Path
     )
-    .map_err(|error| utils::err(&format!("{}", error)))?)
+    .map(|page| page.results)
+    .map_err(utils::index_err)?)
+}
+
+#[hdk_extern]
+pub fn get_links_by_entry_time_for_time_span(input: GetAddressesSinceInput) -> ExternResult<Vec<Link>> {
+    Ok(hc_time_index::get_links_for_time_span(
+        input.index,
+        IndexQuery {
+            link_tag: input.link_tag.into(),
+            sort_key: Some(SortKey::EntryTime),
+            limit: Some(10),
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::default(),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map(|page| page.results)
+    .map_err(utils::index_err)?)
+}
+
+/// Structured tag attached to index links with encode_link_tag
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TestTag {
+    pub kind: String,
+}
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+pub struct IndexTaggedEntryInput {
+    pub entry: TestEntry,
+    pub kind: String,
+}
+
+#[hdk_extern]
+pub fn index_tagged_entry(input: IndexTaggedEntryInput) -> ExternResult<()> {
+    create_entry(&EntryTypes::TestEntry(input.entry.clone()))?;
+    let link_tag = encode_link_tag(&TestTag { kind: input.kind }).map_err(utils::index_err)?;
+    hc_time_index::index_entry(String::from("test_index"), input.entry, link_tag, LinkTypes::Index, LinkTypes::Path)
+        .map_err(utils::index_err)?;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+pub struct GetTaggedLinksInput {
+    pub index: String,
+    pub from: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub kind: Option<String>,
+}
+
+#[hdk_extern]
+pub fn get_tagged_links_for_time_span(input: GetTaggedLinksInput) -> ExternResult<Vec<(Link, TestTag)>> {
+    let link_tag = match input.kind {
+        Some(kind) => LinkTagFilter::Exact(
+            encode_link_tag(&TestTag { kind }).map_err(utils::index_err)?,
+        ),
+        None => LinkTagFilter::All,
+    };
+    hc_time_index::get_tagged_links_for_time_span(
+        input.index,
+        IndexQuery::new(TimeRange::new(input.from, input.until)).with_tag(link_tag),
+        QueryOptions::default(),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map(|page| page.results)
+    .map_err(utils::index_err)
 }
 
 #[hdk_extern]
 pub fn get_links_and_load_for_time_span(input: GetAddressesSinceInput) -> ExternResult<Vec<TestEntry>> {
     Ok(hc_time_index::get_links_and_load_for_time_span(
         input.index,
-        input.from,
-        input.until,
-        input.link_tag,
-        hc_time_index::SearchStrategy::Dfs,
-        Some(10),
+        IndexQuery {
+            link_tag: input.link_tag.into(),
+            strategy: hc_time_index::SearchStrategy::Dfs,
+            limit: Some(10),
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::default(),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map(|page| page.results)
+    .map_err(utils::index_err)?)
+}
+
+#[hdk_extern]
+pub fn get_indexed_items_for_time_span(input: GetAddressesSinceInput) -> ExternResult<Vec<IndexedItem<TestEntry>>> {
+    Ok(hc_time_index::get_indexed_items_for_time_span(
+        input.index,
+        IndexQuery {
+            link_tag: input.link_tag.into(),
+            strategy: hc_time_index::SearchStrategy::Dfs,
+            limit: Some(10),
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::content(),
         LinkTypes::Index,
         LinkTypes::Path
     )
-    .map_err(|error| utils::err(&format!("{}", error)))?)
+    .map(|page| page.results)
+    .map_err(utils::index_err)?)
+}
+
+#[hdk_extern]
+pub fn get_indexed_items_for_time_span_lenient(input: GetAddressesSinceInput) -> ExternResult<(Vec<IndexedItem<TestEntry>>, Vec<(Link, String)>)> {
+    let (page, skipped) = hc_time_index::get_indexed_items_for_time_span_lenient(
+        input.index,
+        IndexQuery {
+            link_tag: input.link_tag.into(),
+            limit: input.limit,
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::content(),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map_err(utils::index_err)?;
+    Ok((page.results, skipped.into_iter().map(|(link, error)| (link, format!("{}", error))).collect()))
+}
+
+/// Index whose link points at the agent's key, so the target can be found but is not a TestEntry
+#[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+pub struct AgentIndex {
+    pub created: DateTime<Utc>,
+}
+
+impl IndexableEntry for AgentIndex {
+    fn entry_time(&self) -> DateTime<Utc> {
+        self.created
+    }
+
+    fn hash(&self) -> ExternResult<EntryHash> {
+        Ok(agent_info()?.agent_initial_pubkey.into())
+    }
+}
+
+#[hdk_extern]
+pub fn index_agent(input: AgentIndex) -> ExternResult<()> {
+    hc_time_index::index_entry(String::from("test_index"), input, LinkTag::new("test"), LinkTypes::Index, LinkTypes::Path)
+        .map_err(utils::index_err)
+}
+
+#[hdk_extern]
+pub fn get_links_with_budget(input: GetAddressesSinceInput) -> ExternResult<Page<Link>> {
+    Ok(hc_time_index::get_links_for_time_span(
+        input.index,
+        IndexQuery {
+            link_tag: input.link_tag.into(),
+            limit: input.limit,
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::default().with_budget(QueryBudget {
+            max_host_calls: None,
+            max_chunks: Some(1),
+        }),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map_err(utils::index_err)?)
+}
+
+#[hdk_extern]
+pub fn get_indexed_items_with_budget(input: GetAddressesSinceInput) -> ExternResult<Page<IndexedItem<TestEntry>>> {
+    Ok(hc_time_index::get_indexed_items_for_time_span(
+        input.index,
+        IndexQuery {
+            link_tag: input.link_tag.into(),
+            strategy: hc_time_index::SearchStrategy::Bfs,
+            limit: input.limit,
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::default().with_budget(QueryBudget {
+            max_host_calls: None,
+            max_chunks: Some(1),
+        }),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map_err(utils::index_err)?)
+}
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+pub struct GetWithHostCallBudgetInput {
+    pub index: String,
+    pub from: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub max_host_calls: usize,
+}
+
+#[hdk_extern]
+pub fn get_indexed_items_with_host_call_budget(input: GetWithHostCallBudgetInput) -> ExternResult<Page<IndexedItem<TestEntry>>> {
+    Ok(hc_time_index::get_indexed_items_for_time_span(
+        input.index,
+        IndexQuery {
+            strategy: hc_time_index::SearchStrategy::Bfs,
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::default().with_budget(QueryBudget {
+            max_host_calls: Some(input.max_host_calls),
+            max_chunks: None,
+        }),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map_err(utils::index_err)?)
+}
+
+#[hdk_extern]
+pub fn get_indexed_items_dfs_with_host_call_budget(input: GetWithHostCallBudgetInput) -> ExternResult<Page<IndexedItem<TestEntry>>> {
+    Ok(hc_time_index::get_indexed_items_for_time_span(
+        input.index,
+        IndexQuery {
+            strategy: hc_time_index::SearchStrategy::Dfs,
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::default().with_budget(QueryBudget {
+            max_host_calls: Some(input.max_host_calls),
+            max_chunks: None,
+        }),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map_err(utils::index_err)?)
+}
+
+#[hdk_extern]
+pub fn explain_indexed_items_for_time_span(input: GetAddressesSinceInput) -> ExternResult<(Page<IndexedItem<TestEntry>>, explain::QueryPlan)> {
+    Ok(hc_time_index::explain_indexed_items_for_time_span(
+        input.index,
+        IndexQuery {
+            link_tag: input.link_tag.into(),
+            strategy: hc_time_index::SearchStrategy::Dfs,
+            limit: input.limit,
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::default(),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map_err(utils::index_err)?)
+}
+
+#[hdk_extern]
+pub fn explain_bfs_indexed_items_for_time_span(input: GetAddressesSinceInput) -> ExternResult<(Page<IndexedItem<TestEntry>>, explain::QueryPlan)> {
+    Ok(hc_time_index::explain_indexed_items_for_time_span(
+        input.index,
+        IndexQuery {
+            link_tag: input.link_tag.into(),
+            strategy: hc_time_index::SearchStrategy::Bfs,
+            limit: input.limit,
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::default(),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map_err(utils::index_err)?)
+}
+
+#[hdk_extern]
+pub fn explain_auto_indexed_items_for_time_span(input: GetAddressesSinceInput) -> ExternResult<(Page<IndexedItem<TestEntry>>, explain::QueryPlan)> {
+    Ok(hc_time_index::explain_indexed_items_for_time_span(
+        input.index,
+        IndexQuery {
+            link_tag: input.link_tag.into(),
+            strategy: hc_time_index::SearchStrategy::Auto,
+            limit: input.limit,
+            ..IndexQuery::new(TimeRange::new(input.from, input.until))
+        },
+        QueryOptions::default(),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map_err(utils::index_err)?)
+}
+
+#[hdk_extern]
+pub fn explain_with_snapshot(input: GetAddressesSinceInput) -> ExternResult<Vec<explain::QueryPlan>> {
+    let snapshot = std::rc::Rc::new(std::cell::RefCell::new(snapshot::TimeTreeSnapshot::new()));
+    let mut plans = vec![];
+    for _ in 0..2 {
+        let (_page, plan) = hc_time_index::explain_links_for_time_span(
+            input.index.clone(),
+            IndexQuery {
+                link_tag: input.link_tag.clone().into(),
+                limit: input.limit,
+                ..IndexQuery::new(TimeRange::new(input.from, input.until))
+            },
+            QueryOptions::default().with_snapshot(snapshot.clone()),
+            LinkTypes::Index,
+            LinkTypes::Path
+        )
+        .map_err(utils::index_err)?;
+        plans.push(plan);
+    }
+    Ok(plans)
+}
+
+#[hdk_extern]
+pub fn get_indexed_items_for_open_range(input: GetCurrentAddressesInput) -> ExternResult<Vec<IndexedItem<TestEntry>>> {
+    hc_time_index::get_indexed_items_for_time_span::<TestEntry, _, _>(
+        input.index,
+        IndexQuery {
+            link_tag: input.link_tag.into(),
+            limit: input.limit,
+            ..IndexQuery::new(TimeRange::between(Bound::Unbounded, Bound::Unbounded, Order::Asc))
+        },
+        QueryOptions::content(),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map(|page| page.results)
+    .map_err(utils::index_err)
+}
+
+#[hdk_extern]
+pub fn get_index_bounds(index: String) -> ExternResult<Option<(entries::Index, entries::Index)>> {
+    hc_time_index::get_index_bounds(index, LinkTypes::Path)
+        .map_err(utils::index_err)
+}
+
+#[hdk_extern]
+pub fn get_latest_index_with_links(index: String) -> ExternResult<Option<entries::Index>> {
+    hc_time_index::methods::get_latest_index_with_links(index, LinkTypes::Index, *MAX_FUTURE_SKEW, LinkTypes::Path)
+        .and_then(|path| path.map(entries::Index::try_from).transpose())
+        .map_err(utils::index_err)
+}
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+pub struct GetLatestIndexInput {
+    pub index: String,
+    pub max_future_skew_secs: Option<u64>,
+}
+
+/// Length of the chunks of this DNA in milliseconds
+#[hdk_extern]
+pub fn get_chunk_interval(_: ()) -> ExternResult<u64> {
+    Ok(MAX_CHUNK_INTERVAL.as_millis() as u64)
+}
+
+#[hdk_extern]
+pub fn get_latest_index(input: GetLatestIndexInput) -> ExternResult<Option<entries::Index>> {
+    let max_future_skew = input
+        .max_future_skew_secs
+        .map(std::time::Duration::from_secs)
+        .unwrap_or(*MAX_FUTURE_SKEW);
+    hc_time_index::methods::get_latest_index(input.index, max_future_skew, LinkTypes::Path)
+        .and_then(|path| path.map(entries::Index::try_from).transpose())
+        .map_err(utils::index_err)
+}
+
+/// Write the branch an agent whose clock ran far ahead would have written, under the year 2099. The chunk at its end covers the
+/// current time so that the link to it still passes validation
+#[hdk_extern]
+pub fn create_future_path(index: String) -> ExternResult<()> {
+    let interval = MAX_CHUNK_INTERVAL.as_millis() as i64;
+    let now = sys_time()?.as_millis();
+    let from = now - now % interval;
+    let chunk = entries::Index {
+        from: std::time::Duration::from_millis(from as u64),
+        until: std::time::Duration::from_millis((from + interval) as u64),
+    };
+    let component = |bytes: Result<SerializedBytes, SerializedBytesError>| {
+        bytes
+            .map(|bytes| Component::from(bytes.bytes().to_owned()))
+            .map_err(|error| wasm_error!(WasmErrorInner::Serialize(error)))
+    };
+    let mut components = vec![component(SerializedBytes::try_from(entries::StringIndex(index)))?];
+    for time in [2099, 1, 1, 0, 0] {
+        components.push(component(SerializedBytes::try_from(entries::TimeIndex(time)))?);
+    }
+    components.push(component(SerializedBytes::try_from(chunk))?);
+    Path::from(components).typed(LinkTypes::Path)?.ensure()
+}
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+pub struct PruneBeforeInput {
+    pub index: String,
+    pub cutoff: DateTime<Utc>,
+    pub max_chunks: usize,
+    pub resume_from: Option<DateTime<Utc>>,
+    pub max_host_calls: Option<usize>,
+}
+
+#[hdk_extern]
+pub fn prune_before(input: PruneBeforeInput) -> ExternResult<PruneReport> {
+    hc_time_index::prune_before(
+        input.index,
+        input.cutoff,
+        input.max_chunks,
+        input.resume_from,
+        QueryOptions::default().with_budget(QueryBudget {
+            max_host_calls: input.max_host_calls,
+            max_chunks: None,
+        }),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map_err(utils::index_err)
+}
+
+#[hdk_extern]
+pub fn remove_author_links(input: GetAddressesSinceInput) -> ExternResult<Vec<ChunkRemovals>> {
+    hc_time_index::remove_author_links(
+        input.index,
+        TimeRange::new(input.from, input.until),
+        LinkTypes::Index,
+        LinkTypes::Path
+    )
+    .map_err(utils::index_err)
 }
 
 #[derive(Serialize, Deserialize, SerializedBytes, Debug)]
@@ -85,11 +579,30 @@ pub fn get_current_addresses(
 ) -> ExternResult<Option<EntryChunkIndex>> {
     Ok(
         hc_time_index::get_current_index(input.index, input.link_tag, LinkTypes::Path)
-            .map_err(|error| utils::err(&format!("{}", error)))?,
+            .map_err(utils::index_err)?,
     )
 }
 
 #[hdk_extern]
 pub fn remove_index(address: EntryHash) -> ExternResult<()> {
-    Ok(hc_time_index::remove_index(address, LinkTypes::Index).map_err(|error| utils::err(&format!("{}", error)))?)
+    Ok(hc_time_index::remove_index(address, LinkTagFilter::All, LinkTypes::Index).map_err(utils::index_err)?)
+}
+
+#[hdk_extern]
+pub fn remove_index_and_log(address: EntryHash) -> ExternResult<()> {
+    hc_time_index::remove_index_and_log(String::from("test_index"), address, LinkTagFilter::All, LinkTypes::Index, LinkTypes::Path)
+        .map_err(utils::index_err)
+}
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+pub struct GetChangesSinceInput {
+    pub index: String,
+    pub since: DateTime<Utc>,
+}
+
+#[hdk_extern]
+pub fn get_changes_since(input: GetChangesSinceInput) -> ExternResult<Vec<IndexChange>> {
+    hc_time_index::get_changes_since(input.index, input.since, QueryOptions::default(), LinkTypes::Index, LinkTypes::Path)
+        .map(|page| page.results)
+        .map_err(utils::index_err)
 }
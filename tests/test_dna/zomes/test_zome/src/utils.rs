@@ -1,5 +1,10 @@
 use hdk::prelude::*;
+use hc_time_index::errors::IndexError;
 
 pub (crate) fn err(reason: &str) -> WasmError {
     wasm_error!(WasmErrorInner::Host(String::from(reason)))
+}
+
+pub (crate) fn index_err(error: IndexError) -> WasmError {
+    err(&format!("{}", error))
 }
\ No newline at end of file
@@ -32,4 +32,19 @@ impl IndexableEntry for TestEntry {
 pub enum LinkTypes {
     Index,
     Path
-}
\ No newline at end of file
+}
+
+#[hdk_extern]
+pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
+    match op {
+        Op::RegisterCreateLink(RegisterCreateLink { create_link }) => {
+            let create_link = create_link.hashed.content;
+            match LinkTypes::from_type(create_link.zome_index, create_link.link_type)? {
+                Some(LinkTypes::Path) => hc_time_index::validate_chunk_link(&create_link)
+                    .map_err(|error| wasm_error!(WasmErrorInner::Guest(format!("{}", error)))),
+                _ => Ok(ValidateCallbackResult::Valid),
+            }
+        }
+        _ => Ok(ValidateCallbackResult::Valid),
+    }
+}